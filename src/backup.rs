@@ -0,0 +1,119 @@
+use crate::{schema, task::Task};
+use anyhow::anyhow;
+use chrono::Utc;
+use colored::Colorize;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Timestamped copies of a journal taken before it is overwritten, kept in a
+/// `.backups` directory next to it. Only the `keep` most recent ones are kept.
+#[derive(Debug, Clone)]
+pub struct Backups {
+    dir: PathBuf,
+    keep: usize,
+}
+
+impl Backups {
+    pub fn for_journal(journal: &Path, keep: usize) -> Backups {
+        Backups {
+            dir: journal.with_extension("backups"),
+            keep,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Saves `content` (a journal as written by [`schema::to_json`]) as the
+    /// newest backup and drops the oldest ones beyond `keep`.
+    pub fn create(&self, content: &[u8]) -> anyhow::Result<()> {
+        if self.keep == 0 {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+        // In UTC, so the names still sort in the order the backups were taken
+        // across a daylight saving change or a move to another zone. The
+        // counter keeps backups taken within the same millisecond apart.
+        let stamp = Utc::now().format("%Y%m%d-%H%M%S%.3fZ").to_string();
+        let path = (0..)
+            .map(|n| self.dir.join(format!("{}-{:03}.json", stamp, n)))
+            .find(|path| !path.exists())
+            .unwrap_or_default();
+        fs::write(path, content)?;
+        self.list()?
+            .into_iter()
+            .skip(self.keep)
+            .try_for_each(fs::remove_file)?;
+
+        Ok(())
+    }
+
+    /// The backups, newest first.
+    pub fn list(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => Err(e)?,
+        };
+        let mut backups = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                backups.push(path);
+            }
+        }
+        backups.sort_by(|a, b| b.cmp(a));
+
+        Ok(backups)
+    }
+
+    /// Reads the `n`-th newest backup (starting at 1), checking it is a valid task list.
+    pub fn read(&self, n: usize) -> anyhow::Result<Vec<Task>> {
+        let backups = self.list()?;
+        let path = n
+            .checked_sub(1)
+            .and_then(|index| backups.get(index))
+            .ok_or(anyhow!(
+                "{}{}",
+                "error: Invalid backup index! the backup index should be between 1 and "
+                    .bright_red(),
+                backups.len().to_string().bright_red()
+            ))?;
+        read_backup(path)
+    }
+}
+
+pub fn read_backup(path: &Path) -> anyhow::Result<Vec<Task>> {
+    let content = fs::read(path)?;
+    schema::from_json(&content).map_err(|e| {
+        anyhow!(
+            "{}{}\n{}",
+            "error: The backup is not a valid task list: ".bright_red(),
+            path.display().to_string().bright_red(),
+            e
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_and_read() {
+        let dir = std::env::temp_dir().join(format!("ttd-test-backup-{}", std::process::id()));
+        let backups = Backups::for_journal(&dir.join(".ttd.json"), 2);
+        backups.create(b"[]").unwrap();
+        backups.create(b"not json").unwrap();
+        backups.create(b"[]").unwrap();
+        assert_eq!(backups.list().unwrap().len(), 2);
+        assert!(backups.read(1).unwrap().is_empty());
+        assert!(backups.read(2).is_err());
+        assert!(backups.read(3).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand};
+use crate::store::Backend;
+use clap::{ArgGroup, Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -9,6 +10,18 @@ pub struct Cli {
     /// Show the path of the journal file.
     #[arg(short, long)]
     pub path: bool,
+
+    /// Use another journal, either a path or a name defined in the config file.
+    #[arg(short, long, global = true, env = "TTD_JOURNAL")]
+    pub journal: Option<String>,
+
+    /// Run the command as if today were another day, in the date format of the config file.
+    #[arg(long, global = true, value_name = "DATE")]
+    pub today: Option<String>,
+
+    /// Show dates and times in another time zone, e.g. `Europe/Paris`, instead of the one of the config file.
+    #[arg(long, global = true, value_name = "ZONE")]
+    pub tz: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -21,15 +34,20 @@ pub enum Commands {
         #[arg(required_unless_present = "multiple")]
         text: Option<String>,
 
-        /// set repeat weekday for the task
+        /// set repeat weekdays for the task, e.g. mon, mon,wed,fri, mon-fri, weekdays or weekend, with an optional time as mon@09:00
         #[arg(short, long = "week", group = "add_args")]
         weekday: Option<String>,
 
-        /// set repeat monthday for the task
-        #[arg(short = 'm', long = "month", group = "add_args")]
-        day: Option<usize>,
+        /// set repeat monthday for the task: 1 to 31, or counted from the end as last, last-1 or -2, with an optional time as 15@09:00
+        #[arg(
+            short = 'm',
+            long = "month",
+            group = "add_args",
+            allow_hyphen_values = true
+        )]
+        day: Option<String>,
 
-        /// set one-time-date for the task
+        /// set one-time-date for the task, with an optional time as 20240402T14:30 or tomorrow@14:30
         #[arg(short = 'o', long = "once", group = "add_args")]
         date: Option<String>,
 
@@ -37,6 +55,14 @@ pub enum Commands {
         #[arg(short, long, group = "add_args")]
         progress: Option<String>,
 
+        /// set a recurrence rule for the task, e.g. FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH
+        #[arg(short, long = "repeat", group = "add_args")]
+        rule: Option<String>,
+
+        /// show a one-time task as due soon this long before its date, e.g. 3d or 1w
+        #[arg(long, requires = "date")]
+        remind: Option<String>,
+
         /// Add multiple tasks to the journal file.
         #[arg(
             visible_alias = "mul",
@@ -50,8 +76,8 @@ pub enum Commands {
     /// Remove one or multiple tasks from the journal file, If no arguments provided, the last task will be removed.
     #[command(visible_aliases = ["r", "rm"])]
     Remove {
-        /// remove a task by id
-        id: Option<usize>,
+        /// remove a task by index or uid
+        id: Option<String>,
 
         /// remove all expired tasks
         #[arg(short, long, exclusive = true)]
@@ -69,10 +95,18 @@ pub enum Commands {
         #[arg(short, long = "once", exclusive = true)]
         once_task: bool,
 
-        /// remove all tasks with repeat weekday
+        /// remove all tasks with repeat weekdays
         #[arg(short, long = "week", exclusive = true)]
         week_task: bool,
 
+        /// remove all tasks with a recurrence rule
+        #[arg(short, long = "recurring", exclusive = true)]
+        recurring_task: bool,
+
+        /// remove all tasks marked as done
+        #[arg(short, long, exclusive = true)]
+        done: bool,
+
         /// remove all tasks
         #[arg(short, long, exclusive = true)]
         all: bool,
@@ -97,22 +131,179 @@ pub enum Commands {
         #[arg(short, long = "once", exclusive = true)]
         once_task: bool,
 
-        /// list all tasks with repeat weekday
+        /// list all tasks with repeat weekdays
         #[arg(short, long = "week", exclusive = true)]
         week_task: bool,
+
+        /// list all tasks with a recurrence rule
+        #[arg(short, long = "recurring", exclusive = true)]
+        recurring_task: bool,
+
+        /// list all tasks marked as done
+        #[arg(short, long, exclusive = true)]
+        done: bool,
     },
 
     /// List tasks to be done today.
     #[command(visible_aliases = ["t", "td"])]
     Today,
 
+    /// List the tasks due on each day of a date range, the next 7 days by default.
+    #[command(visible_aliases = ["ag"])]
+    Agenda {
+        /// set the first day of the range, today by default
+        #[arg(long)]
+        from: Option<String>,
+
+        /// set the last day of the range, 6 days after the first by default
+        #[arg(long)]
+        to: Option<String>,
+    },
+
+    /// Show a month as a calendar with the number of tasks due on each day.
+    Cal {
+        /// set the month to show as YYYYMM, the current month by default
+        month: Option<String>,
+
+        /// list the tasks of the month under the calendar
+        #[arg(short, long)]
+        list: bool,
+    },
+
+    /// Change the text, type or date of a task, keeping its index and uid.
+    #[command(visible_aliases = ["e"])]
+    #[command(group = ArgGroup::new("edit_args").required(true).multiple(true))]
+    Edit {
+        /// set the index or uid of the task to be edited
+        id: String,
+
+        /// set new content for the task
+        #[arg(short, long, group = "edit_args")]
+        text: Option<String>,
+
+        /// set new repeat weekdays for the task
+        #[arg(short, long = "week", group = "edit_args", conflicts_with_all = ["day", "date", "progress", "rule"])]
+        weekday: Option<String>,
+
+        /// set new repeat monthday for the task
+        #[arg(short = 'm', long = "month", group = "edit_args", allow_hyphen_values = true, conflicts_with_all = ["date", "progress", "rule"])]
+        day: Option<String>,
+
+        /// set new one-time-date for the task
+        #[arg(
+            short = 'o',
+            long = "once",
+            group = "edit_args",
+            conflicts_with_all = ["progress", "rule"]
+        )]
+        date: Option<String>,
+
+        /// set new bookmark for the task
+        #[arg(short, long, group = "edit_args")]
+        progress: Option<String>,
+
+        /// set new recurrence rule for the task
+        #[arg(
+            short,
+            long = "repeat",
+            group = "edit_args",
+            conflicts_with = "progress"
+        )]
+        rule: Option<String>,
+
+        /// set new reminder lead time for a one-time task
+        #[arg(long, group = "edit_args", conflicts_with_all = ["weekday", "day", "progress", "rule"])]
+        remind: Option<String>,
+
+        /// edit the task as JSON in $EDITOR
+        #[arg(long, group = "edit_args", conflicts_with_all = ["text", "weekday", "day", "date", "progress", "rule", "remind"])]
+        editor: bool,
+    },
+
+    /// Mark a task as done. Repeating tasks are only done until their next occurrence.
+    #[command(visible_aliases = ["d"])]
+    Done {
+        /// set the index or uid of the task to be marked as done
+        id: String,
+    },
+
     /// update page of the selected bookmark
     #[command(visible_aliases = ["u", "ud"])]
     Update {
-        /// set the id of the bookmark to be updated
-        id: usize,
+        /// set the index or uid of the bookmark to be updated
+        id: String,
 
         /// set the page of the bookmark to be updated
         new_progress: String,
     },
+
+    /// Revert the last change to the journal.
+    Undo,
+
+    /// Reapply the last change reverted by undo.
+    Redo,
+
+    /// List recent changes to the journal.
+    History,
+
+    /// Check the journal for broken tasks, and fix them with --fix.
+    Doctor {
+        /// renumber the tasks and move the broken ones to a quarantine file
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// Move the journal to another storage backend.
+    Migrate {
+        /// set the backend to move the journal to
+        #[arg(long, value_enum)]
+        to: Backend,
+    },
+
+    /// List the backups of the journal or restore one of them.
+    Backup {
+        #[command(subcommand)]
+        action: BackupAction,
+    },
+
+    /// Show or change the settings in the config file.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Print the value of a setting.
+    Get {
+        /// the setting to print, e.g. `date_format` or `greeting.evening`
+        key: String,
+    },
+
+    /// Change the value of a setting.
+    Set {
+        /// the setting to change, e.g. `date_format` or `journals.work`
+        key: String,
+
+        /// the new value
+        value: String,
+    },
+
+    /// Print every setting and the path of the config file.
+    #[command(visible_alias = "ls")]
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum BackupAction {
+    /// List the backups of the journal, newest first.
+    #[command(visible_alias = "ls")]
+    List,
+
+    /// Replace the tasks with the ones from a backup. Can be reverted with undo.
+    Restore {
+        /// set the number of the backup to restore, as shown by `ttd backup list`
+        n: usize,
+    },
 }
@@ -0,0 +1,379 @@
+use crate::{date, store::TaskFilter, task::MonthOverflow};
+use anyhow::anyhow;
+use chrono_tz::Tz;
+use colored::{Color, Colorize};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// User settings read from `$XDG_CONFIG_HOME/ttd/config.toml`
+/// (`~/.config/ttd/config.toml` when the variable is not set).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Journal used when neither `--journal` nor `TTD_JOURNAL` is given.
+    pub journal: Option<String>,
+
+    /// Format of the dates entered with `-o`, in `chrono` strftime syntax.
+    pub date_format: String,
+
+    /// Tasks shown by `ttd l` when no filter is given.
+    pub default_filter: TaskFilter,
+
+    /// What new monthly tasks do in the months that don't have their day.
+    pub month_overflow: MonthOverflow,
+
+    /// Lead time of the reminder of new once-time tasks added without `--remind`, e.g. `1d`.
+    pub remind: Option<String>,
+
+    /// Zone in which dates are worked out and times are shown, e.g. `Europe/Paris`,
+    /// the one of the system when not set.
+    pub timezone: Option<String>,
+
+    /// Set to `false` to disable colored output.
+    pub color: bool,
+
+    pub colors: Colors,
+
+    pub greeting: Greeting,
+
+    /// How many previous versions of the journal are kept as backups, `0` to keep none.
+    pub backups: usize,
+
+    /// Named journals, e.g. `work = "~/Documents/work.json"`.
+    pub journals: BTreeMap<String, PathBuf>,
+}
+
+/// Hours at which `ttd t` stops saying good morning and good afternoon.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct Greeting {
+    pub afternoon: u32,
+    pub evening: u32,
+}
+
+/// Colors of the states of tasks in listings, by the names `colored` knows,
+/// e.g. `red` or `bright cyan`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Colors {
+    pub done: String,
+    pub expired: String,
+    pub due_soon: String,
+    pub ongoing: String,
+    pub upcoming: String,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            journal: None,
+            date_format: date::DATE_FORMAT.to_string(),
+            default_filter: TaskFilter::All,
+            month_overflow: MonthOverflow::Clamp,
+            remind: None,
+            timezone: None,
+            color: true,
+            colors: Colors::default(),
+            greeting: Greeting::default(),
+            backups: 10,
+            journals: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for Greeting {
+    fn default() -> Greeting {
+        Greeting {
+            afternoon: 12,
+            evening: 17,
+        }
+    }
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors {
+            done: "bright cyan".to_string(),
+            expired: "bright red".to_string(),
+            due_soon: "bright magenta".to_string(),
+            ongoing: "bright green".to_string(),
+            upcoming: "bright yellow".to_string(),
+        }
+    }
+}
+
+static COLORS: OnceLock<Colors> = OnceLock::new();
+
+impl Colors {
+    /// Makes these the colors of the listings for the rest of the run. Like
+    /// the override `color` sets on `colored`, it is done once at startup.
+    pub fn install(self) {
+        let _ = COLORS.set(self);
+    }
+
+    /// The installed colors, or the defaults.
+    pub fn current() -> &'static Colors {
+        COLORS.get_or_init(Colors::default)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let colors = [
+            &self.done,
+            &self.expired,
+            &self.due_soon,
+            &self.ongoing,
+            &self.upcoming,
+        ];
+        match colors
+            .into_iter()
+            .find(|color| color.parse::<Color>().is_err())
+        {
+            Some(color) => Err(anyhow!(
+                "{}{}{}",
+                "error: Unknown color: ".bright_red(),
+                color.bright_red(),
+                ", expected a name like red or bright cyan".bright_red()
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file, falling back to the defaults if there is none.
+    pub fn load() -> anyhow::Result<Config> {
+        Config::load_from(&config_path()?)
+    }
+
+    pub fn load_from(path: &Path) -> anyhow::Result<Config> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => Err(e)?,
+        };
+        Config::parse(&content).map_err(|e| {
+            anyhow!(
+                "{}{}\n{}",
+                "error: Invalid config file: ".bright_red(),
+                path.display().to_string().bright_red(),
+                e
+            )
+        })
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        self.save_to(&config_path()?)
+    }
+
+    pub fn save_to(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Config> {
+        let config: Config = toml::from_str(content)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        date::check_date_format(&self.date_format)?;
+        if let Some(remind) = &self.remind {
+            date::parse_lead_time(remind)?;
+        }
+        if let Some(zone) = &self.timezone {
+            date::parse_zone(zone)?;
+        }
+        self.colors.validate()?;
+        let Greeting { afternoon, evening } = self.greeting;
+        if afternoon > evening || evening > 24 {
+            return Err(anyhow!(
+                "{}",
+                "error: Invalid greeting hours, expected 0 <= afternoon <= evening <= 24"
+                    .bright_red()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the value of `--journal` / `TTD_JOURNAL`: either the name of a
+    /// journal defined in the config file, or a path.
+    pub fn journal_path(&self, journal: &str) -> PathBuf {
+        match self.journals.get(journal) {
+            Some(path) => expand_home(path),
+            None => expand_home(Path::new(journal)),
+        }
+    }
+
+    /// The zone set with `timezone`, or the one of the system.
+    pub fn zone(&self) -> Tz {
+        self.timezone
+            .as_deref()
+            .and_then(|zone| date::parse_zone(zone).ok())
+            .unwrap_or_else(date::system_zone)
+    }
+
+    /// Every setting as `key = value` pairs, with dotted keys for nested tables.
+    pub fn entries(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let mut entries = Vec::new();
+        flatten(&toml::Value::try_from(self)?, "", &mut entries);
+
+        Ok(entries)
+    }
+
+    pub fn get(&self, key: &str) -> anyhow::Result<String> {
+        let value = toml::Value::try_from(self)?;
+        key.split('.')
+            .try_fold(&value, |value, part| value.get(part))
+            .map(|value| match value {
+                toml::Value::String(s) => s.clone(),
+                value => value.to_string(),
+            })
+            .or_else(|| OPTIONAL_KEYS.contains(&key).then(String::new))
+            .ok_or_else(|| unknown_key(key))
+    }
+
+    /// Sets `key` to `value`. The value is read as TOML when possible
+    /// (`true`, `17`) and as a plain string otherwise.
+    pub fn set(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        let new_value = toml::from_str::<toml::Table>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+        let mut root = toml::Value::try_from(&*self)?;
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let last = parts.pop().unwrap_or_default();
+        let mut table = &mut root;
+        for part in parts {
+            table = table.get_mut(part).ok_or_else(|| unknown_key(key))?;
+        }
+        let table = table.as_table_mut().ok_or_else(|| unknown_key(key))?;
+        // Named journals are the only table that accepts new keys.
+        if !table.contains_key(last)
+            && !key.starts_with("journals.")
+            && !OPTIONAL_KEYS.contains(&key)
+        {
+            return Err(unknown_key(key));
+        }
+        table.insert(last.to_string(), new_value);
+        *self = Config::parse(&toml::to_string(&root)?)?;
+
+        Ok(())
+    }
+}
+
+/// Settings that are left out of the file while unset.
+const OPTIONAL_KEYS: &[&str] = &["journal", "remind", "timezone"];
+
+pub fn config_path() -> anyhow::Result<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home::home_dir()
+            .map(|home| home.join(".config"))
+            .ok_or(anyhow!("Could not find home directory.".bright_red()))?,
+    };
+
+    Ok(dir.join("ttd").join("config.toml"))
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), home::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn flatten(value: &toml::Value, prefix: &str, entries: &mut Vec<(String, String)>) {
+    match value {
+        toml::Value::Table(table) => table.iter().for_each(|(key, value)| {
+            let key = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            flatten(value, &key, entries);
+        }),
+        value => entries.push((prefix.to_string(), value.to_string())),
+    }
+}
+
+fn unknown_key(key: &str) -> anyhow::Error {
+    anyhow!(
+        "{}{}",
+        "error: Unknown config key: ".bright_red(),
+        key.bright_red()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_path() {
+        let config = Config::parse(
+            r#"
+            [journals]
+            work = "/srv/team/ttd.json"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.journal_path("work"),
+            PathBuf::from("/srv/team/ttd.json")
+        );
+        assert_eq!(
+            config.journal_path("./personal.json"),
+            PathBuf::from("./personal.json")
+        );
+    }
+
+    #[test]
+    fn test_get_and_set() {
+        let mut config = Config::default();
+        assert_eq!(config.get("date_format").unwrap(), "%Y%m%d");
+        config.set("greeting.evening", "20").unwrap();
+        assert_eq!(config.greeting.evening, 20);
+        config.set("default_filter", "week").unwrap();
+        assert_eq!(config.default_filter, TaskFilter::Week);
+        config.set("month_overflow", "roll").unwrap();
+        assert_eq!(config.month_overflow, MonthOverflow::Roll);
+        assert!(config.set("month_overflow", "never").is_err());
+        config.set("journals.work", "~/work.json").unwrap();
+        assert_eq!(config.get("journals.work").unwrap(), "~/work.json");
+
+        assert_eq!(config.get("journal").unwrap(), "");
+        config.set("journal", "work").unwrap();
+        assert_eq!(config.journal.as_deref(), Some("work"));
+
+        assert_eq!(config.get("timezone").unwrap(), "");
+        config.set("timezone", "Asia/Tokyo").unwrap();
+        assert_eq!(config.zone(), Tz::Asia__Tokyo);
+        assert!(config.set("timezone", "Mars/Olympus").is_err());
+        config.set("remind", "2d").unwrap();
+        assert_eq!(config.remind.as_deref(), Some("2d"));
+        assert!(config.set("remind", "soon").is_err());
+
+        assert!(config.set("greeting.afternoon", "21").is_err());
+        assert!(config.set("color", "maybe").is_err());
+        config.set("colors.expired", "red").unwrap();
+        assert_eq!(config.colors.expired, "red");
+        assert!(config.set("colors.done", "plaid").is_err());
+        assert!(config.set("colors.overdue", "red").is_err());
+        assert!(config.set("date_format", "%Q").is_err());
+        assert!(config.set("no_such_key", "1").is_err());
+        assert!(config.get("no_such_key").is_err());
+    }
+}
@@ -1,34 +1,439 @@
-use crate::task::OnceDateStatus;
+use crate::{
+    config::Greeting,
+    task::{MonthOverflow, OnceDateStatus},
+};
 use anyhow::anyhow;
-use chrono::{Datelike, Local, NaiveDate, Timelike, Weekday};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, SubsecRound,
+    TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
 use colored::Colorize;
+use serde::{de, Deserialize, Deserializer};
+use std::env;
 
-pub fn get_greeting() -> String {
-    let hour = Local::now().hour();
-    match hour {
-        0..=11 => "Good morning!".to_string(),
-        12..=16 => "Good afternoon!".to_string(),
-        _ => "Good evening!".to_string(),
+/// Format of the dates stored in the journal.
+pub const DATE_FORMAT: &str = "%Y%m%d";
+
+/// Where ttd reads the current date and time from. Everything that depends on
+/// "today" goes through a clock, so commands can run as if on another day or
+/// in another time zone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clock {
+    source: ClockSource,
+    /// The zone in which dates are worked out and times are shown.
+    zone: Tz,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClockSource {
+    System,
+    /// The given day at the current time of day, set with `--today`.
+    Day(NaiveDate),
+    /// A fixed point in time, on the wall clock of the zone.
+    Fixed(NaiveDateTime),
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock::system()
+    }
+}
+
+impl Clock {
+    pub fn system() -> Clock {
+        Clock {
+            source: ClockSource::System,
+            zone: system_zone(),
+        }
+    }
+
+    pub fn day(day: NaiveDate) -> Clock {
+        Clock {
+            source: ClockSource::Day(day),
+            ..Clock::system()
+        }
+    }
+
+    pub fn fixed(now: NaiveDateTime) -> Clock {
+        Clock {
+            source: ClockSource::Fixed(now),
+            ..Clock::system()
+        }
+    }
+
+    /// The same clock, read in `zone` instead of the system one.
+    pub fn in_zone(self, zone: Tz) -> Clock {
+        Clock { zone, ..self }
+    }
+
+    pub fn now(&self) -> DateTime<Tz> {
+        let system = Utc::now().with_timezone(&self.zone);
+        match self.source {
+            ClockSource::System => system,
+            ClockSource::Day(day) => from_wall_clock(day.and_time(system.time()), self.zone),
+            ClockSource::Fixed(now) => from_wall_clock(now, self.zone),
+        }
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        self.now().date_naive()
+    }
+
+    /// The current time as stored in the journal, to the second.
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        self.now().with_timezone(&Utc).trunc_subsecs(0)
+    }
+
+    pub fn zone(&self) -> Tz {
+        self.zone
+    }
+
+    /// Shows a stored timestamp in the zone of the clock.
+    pub fn format(&self, timestamp: &DateTime<Utc>) -> String {
+        format_timestamp(timestamp, self.zone)
+    }
+}
+
+/// The zone of the system: the one in `TZ`, or else the one the system is set to,
+/// or UTC if neither is known.
+pub fn system_zone() -> Tz {
+    env::var("TZ")
+        .ok()
+        .and_then(|zone| zone.trim_start_matches(':').parse().ok())
+        .or_else(|| iana_time_zone::get_timezone().ok()?.parse().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+/// Parses the name of a time zone from the IANA database, e.g. `Europe/Paris`.
+pub fn parse_zone(zone: &str) -> anyhow::Result<Tz> {
+    zone.trim().parse().map_err(|_| {
+        anyhow!(
+            "{}{}{}",
+            "error: Unknown time zone: ".bright_red(),
+            zone.bright_red(),
+            ", expected a name like Europe/Paris or UTC".bright_red()
+        )
+    })
+}
+
+/// The point in time at which the wall clock of `zone` shows `time`. Times
+/// skipped by a change to daylight saving time are moved past the change.
+pub fn from_wall_clock(time: NaiveDateTime, zone: Tz) -> DateTime<Tz> {
+    zone.from_local_datetime(&time)
+        .earliest()
+        .or_else(|| {
+            zone.from_local_datetime(&(time + Duration::hours(1)))
+                .earliest()
+        })
+        .unwrap_or_else(|| zone.from_utc_datetime(&time))
+}
+
+/// A date and time of day set on the wall clock of `from`, as shown on the one
+/// of `to`. Without a time or a zone there is nothing to move.
+pub fn move_to_zone(
+    date: NaiveDate,
+    time: Option<NaiveTime>,
+    from: Option<Tz>,
+    to: Tz,
+) -> (NaiveDate, Option<NaiveTime>) {
+    match (time, from) {
+        (Some(time), Some(from)) => {
+            let moved = from_wall_clock(date.and_time(time), from)
+                .with_timezone(&to)
+                .naive_local();
+            (moved.date(), Some(moved.time()))
+        }
+        _ => (date, time),
+    }
+}
+
+/// Shows a stored timestamp on the wall clock of `zone`.
+pub fn format_timestamp(timestamp: &DateTime<Utc>, zone: Tz) -> String {
+    timestamp
+        .with_timezone(&zone)
+        .format(TIME_FORMAT)
+        .to_string()
+}
+
+/// Reads a stored timestamp: RFC 3339, or the zone-less format of journals
+/// written before timestamps were stored in UTC, taken to be on the wall
+/// clock of the zone `zone` returns. It is only called for the latter.
+pub fn parse_timestamp(timestamp: &str, zone: impl FnOnce() -> Tz) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(timestamp, TIME_FORMAT)
+                .map(|time| from_wall_clock(time, zone()).with_timezone(&Utc))
+        })
+        .ok()
+}
+
+/// Deserializes a timestamp with [`parse_timestamp`], so the history and the
+/// quarantine of older journals stay readable.
+pub fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let timestamp = String::deserialize(deserializer)?;
+    parse_timestamp(&timestamp, system_zone)
+        .ok_or_else(|| de::Error::custom(format!("invalid timestamp: {}", timestamp)))
+}
+
+pub fn deserialize_optional_timestamp<'de, D>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Timestamp(#[serde(deserialize_with = "deserialize_timestamp")] DateTime<Utc>);
+
+    Ok(Option::<Timestamp>::deserialize(deserializer)?.map(|Timestamp(timestamp)| timestamp))
+}
+
+/// Serde format of the dates of once-time tasks: [`DATE_FORMAT`], also reading
+/// ISO dates so a hand-edited journal can use them.
+pub mod journal_date {
+    use super::*;
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&date.format(DATE_FORMAT))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+        let date = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(&date, DATE_FORMAT)
+            .or_else(|_| NaiveDate::parse_from_str(&date, "%Y-%m-%d"))
+            .map_err(|_| de::Error::custom(format!("invalid date '{}'", date)))
+    }
+}
+
+/// Serde format of the weekdays of weekly tasks: `["Mon", "Fri"]`.
+pub mod journal_weekdays {
+    use super::*;
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(
+        weekdays: &[chrono::Weekday],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(weekdays.iter().map(chrono::Weekday::to_string))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<chrono::Weekday>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|weekday| {
+                parse_weekday(&weekday)
+                    .map_err(|_| de::Error::custom(format!("invalid weekday '{}'", weekday)))
+            })
+            .collect()
     }
 }
 
-pub fn get_time() -> String {
-    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+/// Serde format of the optional times of day of tasks: [`TIME_OF_DAY_FORMAT`].
+pub mod time_of_day {
+    use super::*;
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<NaiveTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.collect_str(&time.format(TIME_OF_DAY_FORMAT)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<NaiveTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|time| {
+                parse_time_of_day(&time)
+                    .map_err(|_| de::Error::custom(format!("invalid time '{}'", time)))
+            })
+            .transpose()
+    }
+}
+
+/// The zone of a task, stored by its IANA name.
+pub mod time_zone {
+    use super::*;
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(zone: &Option<Tz>, serializer: S) -> Result<S::Ok, S::Error> {
+        match zone {
+            Some(zone) => serializer.serialize_str(zone.name()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Tz>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|zone| {
+                zone.parse()
+                    .map_err(|_| de::Error::custom(format!("unknown time zone '{}'", zone)))
+            })
+            .transpose()
+    }
 }
 
-pub fn get_date() -> String {
-    Local::now().format("%Y-%m-%d").to_string()
+pub fn get_greeting(greeting: &Greeting, clock: &Clock) -> String {
+    let hour = clock.now().hour();
+    if hour < greeting.afternoon {
+        "Good morning!".to_string()
+    } else if hour < greeting.evening {
+        "Good afternoon!".to_string()
+    } else {
+        "Good evening!".to_string()
+    }
 }
 
-pub fn parse_date(date: &str) -> anyhow::Result<NaiveDate> {
-    NaiveDate::parse_from_str(date, "%Y%m%d").or(Err(anyhow!(
+/// Format of the timestamps stored in the journal.
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Format of the times of day at which tasks are due.
+pub const TIME_OF_DAY_FORMAT: &str = "%H:%M";
+
+pub fn parse_time_of_day(time: &str) -> anyhow::Result<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), TIME_OF_DAY_FORMAT).or(Err(anyhow!(
         "{}",
-        "error: Invalid date, please enter a valid date (e.g. 20240402)".bright_red()
+        "error: Invalid time, please enter a valid time as HH:MM (e.g. 09:00, 14:30)".bright_red()
     )))
 }
 
-pub fn get_weekday() -> Weekday {
-    Local::now().weekday()
+/// Splits the time of day off a value like `mon@09:00` or `20240402T14:30`.
+pub fn split_time(value: &str) -> anyhow::Result<(&str, Option<NaiveTime>)> {
+    if let Some((value, time)) = value.rsplit_once('@') {
+        return Ok((value, Some(parse_time_of_day(time)?)));
+    }
+    match value.rsplit_once('T') {
+        Some((value, time)) if parse_time_of_day(time).is_ok() => {
+            Ok((value, parse_time_of_day(time).ok()))
+        }
+        _ => Ok((value, None)),
+    }
+}
+
+/// Parses a date entered by the user. Besides a date in `format` (the
+/// `date_format` of the config file), it accepts an ISO date (`2024-04-02`),
+/// the journal format (`20240402`) and dates relative to `today`: `today`,
+/// `tomorrow`, `+3d`, `+2w`, `next fri` and `end of month`.
+pub fn parse_user_date(date: &str, format: &str, today: NaiveDate) -> anyhow::Result<NaiveDate> {
+    let date = date.trim();
+    let lowercase = date.to_lowercase();
+    let relative = match lowercase.split_whitespace().collect::<Vec<_>>()[..] {
+        ["today"] => Some(today),
+        ["tomorrow"] => today.succ_opt(),
+        ["next", weekday] => parse_weekday(weekday)
+            .ok()
+            .map(|weekday| next_weekday(today, weekday)),
+        ["end", "of", "month"] => today.with_day(days_in_month(today)),
+        [offset] if offset.starts_with('+') => parse_offset(&offset[1..], today),
+        _ => None,
+    };
+    relative
+        .or_else(|| NaiveDate::parse_from_str(date, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        .or_else(|| NaiveDate::parse_from_str(date, DATE_FORMAT).ok())
+        .ok_or(anyhow!(
+            "{}",
+            format!(
+                "error: Invalid date, please enter a valid date (e.g. {}, 2024-04-02, today, tomorrow, +3d, +2w, next fri, end of month)",
+                NaiveDate::from_ymd_opt(2024, 4, 2).unwrap().format(format)
+            )
+            .bright_red()
+        ))
+}
+
+/// The first `weekday` after `today`, a week later when today is that weekday.
+fn next_weekday(today: NaiveDate, weekday: chrono::Weekday) -> NaiveDate {
+    let days = (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+    today + chrono::Duration::days(if days == 0 { 7 } else { days.into() })
+}
+
+/// Reads an offset like `3d` or `2w` and adds it to `today`.
+fn parse_offset(offset: &str, today: NaiveDate) -> Option<NaiveDate> {
+    today.checked_add_days(chrono::Days::new(offset_days(offset)?.into()))
+}
+
+/// The number of days in an offset like `3d` or `2w`.
+fn offset_days(offset: &str) -> Option<u32> {
+    let (count, unit) = offset.split_at(offset.len().checked_sub(1)?);
+    let count = count.parse::<u32>().ok()?;
+    match unit {
+        "d" => Some(count),
+        "w" => count.checked_mul(7),
+        _ => None,
+    }
+}
+
+/// Parses how long before its date a once-time task is due soon: `3d`, `2w`,
+/// or a number of days.
+pub fn parse_lead_time(lead_time: &str) -> anyhow::Result<u32> {
+    let lead_time = lead_time.trim().to_lowercase();
+    lead_time
+        .parse::<u32>()
+        .ok()
+        .or_else(|| offset_days(&lead_time))
+        .ok_or(anyhow!(
+            "{}",
+            "error: Invalid lead time, please enter a number of days or weeks (e.g. 3d, 1w)"
+                .bright_red()
+        ))
+}
+
+pub fn check_date_format(format: &str) -> anyhow::Result<()> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(anyhow!(
+            "{}{}",
+            "error: Invalid date format: ".bright_red(),
+            format.bright_red()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Parses a month written as `YYYYMM` into its first day.
+pub fn parse_month(month: &str) -> anyhow::Result<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{}01", month), "%Y%m%d")
+        .ok()
+        .filter(|_| month.len() == 6)
+        .ok_or(anyhow!(
+            "{}",
+            "error: Invalid month, please enter a month as YYYYMM (e.g. 202404)".bright_red()
+        ))
+}
+
+/// The weeks of the month starting on `first`, Monday to Sunday, with `None`
+/// for the days that belong to the previous or the next month.
+pub fn month_weeks(first: NaiveDate) -> Vec<[Option<NaiveDate>; 7]> {
+    let mut weeks = Vec::new();
+    let mut week = [None; 7];
+    for day in first
+        .iter_days()
+        .take_while(|day| day.month() == first.month())
+    {
+        let column = day.weekday().num_days_from_monday() as usize;
+        week[column] = Some(day);
+        if column == 6 {
+            weeks.push(week);
+            week = [None; 7];
+        }
+    }
+    if week.iter().any(Option::is_some) {
+        weeks.push(week);
+    }
+
+    weeks
 }
 
 pub fn parse_weekday(weekday: &str) -> anyhow::Result<chrono::Weekday> {
@@ -39,31 +444,142 @@ pub fn parse_weekday(weekday: &str) -> anyhow::Result<chrono::Weekday> {
     )))
 }
 
-pub fn date_check(date: &str) -> OnceDateStatus {
-    let date = parse_date(date).unwrap();
-    let today_date = Local::now().date_naive();
-    if date == today_date {
-        OnceDateStatus::Ongoing
-    } else if date < today_date {
+/// Parses a set of weekdays: a list like `mon,wed,fri`, a range like `mon-fri`
+/// (which may wrap around the weekend, e.g. `fri-mon`), `weekdays` or `weekend`.
+/// The result starts on Monday and has no duplicates.
+pub fn parse_weekdays(weekdays: &str) -> anyhow::Result<Vec<chrono::Weekday>> {
+    let mut days = Vec::new();
+    for part in weekdays.split(',').map(str::trim) {
+        let (first, last) = match part.to_lowercase().as_str() {
+            "weekdays" => (chrono::Weekday::Mon, chrono::Weekday::Fri),
+            "weekend" => (chrono::Weekday::Sat, chrono::Weekday::Sun),
+            _ => match part.split_once('-') {
+                Some((first, last)) => (parse_weekday(first)?, parse_weekday(last)?),
+                None => (parse_weekday(part)?, parse_weekday(part)?),
+            },
+        };
+        let mut day = first;
+        days.push(day);
+        while day != last {
+            day = day.succ();
+            days.push(day);
+        }
+    }
+    days.sort_by_key(|day| day.num_days_from_monday());
+    days.dedup();
+
+    Ok(days)
+}
+
+/// Shows the weekdays of a weekly task as `Mon,Wed,Fri`.
+pub fn format_weekdays(weekdays: &[chrono::Weekday]) -> String {
+    weekdays
+        .iter()
+        .map(chrono::Weekday::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Where a once-time task stands at `now`. A task with a time expires right
+/// after that time, one without at the end of its day. A task reminded
+/// `remind` days ahead is due soon from that many days before its date.
+pub fn date_check(
+    date: NaiveDate,
+    time: Option<NaiveTime>,
+    remind: Option<u32>,
+    now: NaiveDateTime,
+) -> OnceDateStatus {
+    let past_time = time.is_some_and(|time| now.time() > time);
+    let days_left = (date - now.date()).num_days();
+    if date < now.date() || (date == now.date() && past_time) {
         OnceDateStatus::Expired
+    } else if date == now.date() {
+        OnceDateStatus::Ongoing
+    } else if remind.is_some_and(|remind| days_left <= remind.into()) {
+        OnceDateStatus::DueSoon
     } else {
         OnceDateStatus::Upcoming
     }
 }
 
-pub fn weekday_check(weekday: &str) -> bool {
-    let weekday = parse_weekday(weekday).unwrap();
-    let today_weekday = get_weekday();
-    weekday == today_weekday
+pub fn weekday_check(weekdays: &[chrono::Weekday], today: NaiveDate) -> bool {
+    weekdays.contains(&today.weekday())
 }
 
-pub fn day_check(day: usize) -> bool {
-    let today = Local::now()
-        .format("%d")
-        .to_string()
-        .parse::<usize>()
-        .unwrap();
-    day == today
+/// Whether `completed_on` is on or after the latest day, up to today,
+/// on which a task repeating on the days matched by `occurs` was due.
+pub fn completed_in_current_occurrence(
+    completed_on: NaiveDate,
+    today: NaiveDate,
+    occurs: impl Fn(NaiveDate) -> bool,
+) -> bool {
+    today
+        .iter_days()
+        .rev()
+        .take(366)
+        .find(|d| occurs(*d))
+        .is_some_and(|occurrence| completed_on >= occurrence)
+}
+
+/// Parses the day of a monthly task: `1` to `31`, or a day counted from the end
+/// of the month, either as `last`, `last-1`, ... or as `-1`, `-2`, ...
+pub fn parse_monthday(day: &str) -> anyhow::Result<i32> {
+    let day = day.trim().to_lowercase();
+    let day = match day.strip_prefix("last") {
+        Some("") => -1,
+        Some(before) => before
+            .strip_prefix('-')
+            .and_then(|before| before.parse::<i32>().ok())
+            .map_or(0, |before| -before - 1),
+        None => day.parse::<i32>().unwrap_or(0),
+    };
+    if day == 0 || !(-31..=31).contains(&day) {
+        return Err(anyhow!(
+            "{}",
+            "error: Invalid day, please enter a valid day (e.g. 1, 31, last, last-1, -2, etc.)"
+                .bright_red()
+        ));
+    }
+
+    Ok(day)
+}
+
+/// The day of a monthly task as it is shown, in a form `parse_monthday` reads back.
+pub fn format_monthday(day: i32) -> String {
+    match day {
+        -1 => "last".to_string(),
+        day if day < 0 => format!("last{}", day + 1),
+        day => day.to_string(),
+    }
+}
+
+pub fn days_in_month(day: NaiveDate) -> u32 {
+    let first = day.with_day(1).unwrap_or(day);
+    first
+        .checked_add_months(Months::new(1))
+        .and_then(|next| next.pred_opt())
+        .map_or(31, |last| last.day())
+}
+
+/// Whether a task repeating on `day` of each month is due `today`. Negative days
+/// count from the end of the month, and `overflow` decides what happens in the
+/// months too short for a positive day.
+pub fn day_check(day: i32, overflow: MonthOverflow, today: NaiveDate) -> bool {
+    let last = days_in_month(today) as i32;
+    let today_day = today.day() as i32;
+    let due = match day {
+        day if day < 0 => today_day == (last + day + 1).max(1),
+        day if day <= last => today_day == day,
+        _ => overflow == MonthOverflow::Clamp && today_day == last,
+    };
+    // Rolled over from the previous month, which was too short.
+    let rolled = overflow == MonthOverflow::Roll
+        && today_day == 1
+        && today
+            .pred_opt()
+            .is_some_and(|previous| (previous.day() as i32) < day);
+
+    due || rolled
 }
 
 #[cfg(test)]
@@ -71,11 +587,71 @@ mod tests {
     use std::cmp::Ordering;
 
     use super::*;
+    use chrono::Weekday;
+
+    #[test]
+    fn test_clock() {
+        let now = NaiveDate::from_ymd_opt(2024, 4, 2)
+            .unwrap()
+            .and_hms_opt(14, 30, 0)
+            .unwrap();
+        let clock = Clock::fixed(now).in_zone(Tz::UTC);
+        assert_eq!(clock.today().weekday(), Weekday::Tue);
+        assert_eq!(clock.timestamp().to_rfc3339(), "2024-04-02T14:30:00+00:00");
+        let friday = NaiveDate::from_ymd_opt(2024, 4, 5).unwrap();
+        assert_eq!(Clock::day(friday).today(), friday);
+
+        let tokyo = Clock::fixed(now).in_zone(Tz::Asia__Tokyo);
+        assert_eq!(tokyo.timestamp().to_rfc3339(), "2024-04-02T05:30:00+00:00");
+        assert_eq!(clock.format(&tokyo.timestamp()), "2024-04-02 05:30:00");
+        assert_eq!(tokyo.format(&clock.timestamp()), "2024-04-02 23:30:00");
+    }
+
+    #[test]
+    fn test_zones() {
+        assert_eq!(parse_zone("Europe/Paris").unwrap(), Tz::Europe__Paris);
+        assert_eq!(parse_zone("UTC").unwrap(), Tz::UTC);
+        assert!(parse_zone("Mars/Olympus").is_err());
+
+        // 02:30 doesn't exist in Paris on the day clocks move forward.
+        let skipped = NaiveDate::from_ymd_opt(2024, 3, 31)
+            .unwrap()
+            .and_hms_opt(2, 30, 0)
+            .unwrap();
+        assert_eq!(
+            from_wall_clock(skipped, Tz::Europe__Paris).to_rfc3339(),
+            "2024-03-31T03:30:00+02:00"
+        );
+    }
 
     #[test]
-    fn test_get_weekday() {
-        let time = get_weekday();
-        println!("Today is : {}", time);
+    fn test_move_to_zone() {
+        let date = NaiveDate::from_ymd_opt(2024, 4, 3).unwrap();
+        let time = NaiveTime::from_hms_opt(2, 0, 0);
+        let moved = move_to_zone(date, time, Some(Tz::Asia__Tokyo), Tz::Europe__Paris);
+        assert_eq!(
+            moved,
+            (date.pred_opt().unwrap(), NaiveTime::from_hms_opt(19, 0, 0))
+        );
+        assert_eq!(
+            move_to_zone(date, None, Some(Tz::Asia__Tokyo), Tz::UTC),
+            (date, None)
+        );
+        assert_eq!(move_to_zone(date, time, None, Tz::UTC), (date, time));
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let utc = parse_timestamp("2024-04-01T06:00:00Z", || Tz::UTC).unwrap();
+        assert_eq!(
+            parse_timestamp("2024-04-01T08:00:00+02:00", || Tz::UTC),
+            Some(utc)
+        );
+        assert_eq!(
+            parse_timestamp("2024-04-01 08:00:00", || Tz::Europe__Paris),
+            Some(utc)
+        );
+        assert_eq!(parse_timestamp("not a time", || Tz::UTC), None);
     }
 
     #[test]
@@ -84,16 +660,254 @@ mod tests {
         assert!(weekday.is_err());
     }
 
+    #[test]
+    fn test_parse_weekdays() {
+        use Weekday::*;
+        assert_eq!(parse_weekdays("fri,mon,Wed,mon").unwrap(), [Mon, Wed, Fri]);
+        assert_eq!(
+            parse_weekdays("mon-fri").unwrap(),
+            parse_weekdays("weekdays").unwrap()
+        );
+        assert_eq!(parse_weekdays("mon-fri").unwrap().len(), 5);
+        assert_eq!(parse_weekdays("Weekend").unwrap(), [Sat, Sun]);
+        assert_eq!(parse_weekdays("sat-mon").unwrap(), [Mon, Sat, Sun]);
+        assert_eq!(parse_weekdays("tue").unwrap(), [Tue]);
+        assert!(parse_weekdays("mon,someday").is_err());
+        assert!(parse_weekdays("mon-").is_err());
+        assert!(parse_weekdays("").is_err());
+    }
+
+    /// Parses `date` on Tue 2024-04-02 with a day-first locale format.
+    fn parse_user(date: &str) -> Option<String> {
+        let today = NaiveDate::from_ymd_opt(2024, 4, 2).unwrap();
+        parse_user_date(date, "%d/%m/%Y", today)
+            .ok()
+            .map(|date| date.format(DATE_FORMAT).to_string())
+    }
+
+    #[test]
+    fn test_parse_lead_time() {
+        assert_eq!(parse_lead_time("3d").unwrap(), 3);
+        assert_eq!(parse_lead_time("2W").unwrap(), 14);
+        assert_eq!(parse_lead_time("5").unwrap(), 5);
+        assert!(parse_lead_time("3m").is_err());
+        assert!(parse_lead_time("-1d").is_err());
+        assert!(parse_lead_time("").is_err());
+    }
+
+    #[test]
+    fn test_parse_user_date_today() {
+        assert_eq!(parse_user("today").as_deref(), Some("20240402"));
+        assert_eq!(parse_user(" Today ").as_deref(), Some("20240402"));
+    }
+
+    #[test]
+    fn test_parse_user_date_tomorrow() {
+        assert_eq!(parse_user("tomorrow").as_deref(), Some("20240403"));
+    }
+
+    #[test]
+    fn test_parse_user_date_days_from_today() {
+        assert_eq!(parse_user("+3d").as_deref(), Some("20240405"));
+        assert_eq!(parse_user("+30d").as_deref(), Some("20240502"));
+        assert_eq!(parse_user("+0d").as_deref(), Some("20240402"));
+        assert_eq!(parse_user("+d"), None);
+        assert_eq!(parse_user("+-1d"), None);
+    }
+
+    #[test]
+    fn test_parse_user_date_weeks_from_today() {
+        assert_eq!(parse_user("+2w").as_deref(), Some("20240416"));
+        assert_eq!(parse_user("+2y"), None);
+    }
+
+    #[test]
+    fn test_parse_user_date_next_weekday() {
+        assert_eq!(parse_user("next fri").as_deref(), Some("20240405"));
+        assert_eq!(parse_user("next Monday").as_deref(), Some("20240408"));
+        assert_eq!(parse_user("next tue").as_deref(), Some("20240409"));
+        assert_eq!(parse_user("next someday"), None);
+    }
+
+    #[test]
+    fn test_parse_user_date_end_of_month() {
+        assert_eq!(parse_user("end of month").as_deref(), Some("20240430"));
+        assert_eq!(parse_user("End  of Month").as_deref(), Some("20240430"));
+    }
+
+    #[test]
+    fn test_parse_user_date_iso() {
+        assert_eq!(parse_user("2024-05-01").as_deref(), Some("20240501"));
+        assert_eq!(parse_user("2024-02-30"), None);
+    }
+
+    #[test]
+    fn test_parse_user_date_formats() {
+        assert_eq!(parse_user("01/05/2024").as_deref(), Some("20240501"));
+        assert_eq!(parse_user("20240501").as_deref(), Some("20240501"));
+        assert_eq!(parse_user("someday"), None);
+        assert_eq!(parse_user(""), None);
+    }
+
+    #[test]
+    fn test_split_time() {
+        let time = |hour, min| NaiveTime::from_hms_opt(hour, min, 0);
+        assert_eq!(split_time("mon@09:00").unwrap(), ("mon", time(9, 0)));
+        assert_eq!(
+            split_time("20240402T14:30").unwrap(),
+            ("20240402", time(14, 30))
+        );
+        assert_eq!(
+            split_time("next tue@8:05").unwrap(),
+            ("next tue", time(8, 5))
+        );
+        assert_eq!(split_time("next tue").unwrap(), ("next tue", None));
+        assert_eq!(split_time("20240402").unwrap(), ("20240402", None));
+        assert!(split_time("mon@25:00").is_err());
+        assert!(split_time("mon@").is_err());
+    }
+
     #[test]
     fn test_parse_to_timestamp() {
-        let timestamp_1 = parse_date("20230501").unwrap();
-        let timestam0_2 = parse_date("20230503").unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 4, 2).unwrap();
+        let timestamp_1 = parse_user_date("20230501", DATE_FORMAT, today).unwrap();
+        let timestam0_2 = parse_user_date("20230503", DATE_FORMAT, today).unwrap();
         assert_eq!(timestamp_1.cmp(&timestam0_2), Ordering::Less);
     }
 
     #[test]
     fn test_parse_date() {
-        let date = parse_date("20230501");
+        let today = NaiveDate::from_ymd_opt(2024, 4, 2).unwrap();
+        let date = parse_user_date("20230501", DATE_FORMAT, today);
+        assert!(date.is_ok());
+        let date = parse_user_date("2023-05-01", "%Y-%m-%d", today);
         assert!(date.is_ok());
+        let date = parse_user_date("01/05/2023", "%d/%m/%Y", today);
+        assert_eq!(date.unwrap(), NaiveDate::from_ymd_opt(2023, 5, 1).unwrap());
+        assert!(parse_user_date("2023-13-01", DATE_FORMAT, today).is_err());
+    }
+
+    #[test]
+    fn test_month_weeks() {
+        let first = parse_month("202404").unwrap();
+        let weeks = month_weeks(first);
+        assert_eq!(weeks.len(), 5);
+        assert_eq!(weeks[0][0], Some(first));
+        assert_eq!(weeks[4][1].map(|d| d.day()), Some(30));
+        assert_eq!(weeks[4][2], None);
+
+        let weeks = month_weeks(parse_month("202409").unwrap());
+        assert_eq!(weeks.len(), 6);
+        assert_eq!(weeks[0][5], None);
+        assert_eq!(weeks[0][6].map(|d| d.day()), Some(1));
+        assert!(parse_month("2024").is_err());
+        assert!(parse_month("202413").is_err());
+    }
+
+    #[test]
+    fn test_completed_in_current_occurrence() {
+        let today = NaiveDate::from_ymd_opt(2024, 4, 2).unwrap();
+        let yesterday = today.pred_opt().unwrap();
+        assert!(completed_in_current_occurrence(today, today, |_| true));
+        assert!(completed_in_current_occurrence(yesterday, today, |d| d < today));
+        assert!(!completed_in_current_occurrence(yesterday, today, |d| d == today));
+    }
+
+    #[test]
+    fn test_monthday() {
+        assert_eq!(parse_monthday("31").unwrap(), 31);
+        assert_eq!(parse_monthday("Last").unwrap(), -1);
+        assert_eq!(parse_monthday("-3").unwrap(), -3);
+        assert_eq!(parse_monthday("last-2").unwrap(), -3);
+        assert!(parse_monthday("last-31").is_err());
+        assert!(parse_monthday("last2").is_err());
+        assert!(parse_monthday("0").is_err());
+        assert!(parse_monthday("32").is_err());
+        assert!(parse_monthday("-32").is_err());
+        assert!(parse_monthday("first").is_err());
+        assert_eq!(format_monthday(-1), "last");
+        assert_eq!(format_monthday(-2), "last-1");
+        assert_eq!(format_monthday(31), "31");
+
+        let day = |date: &str| NaiveDate::parse_from_str(date, DATE_FORMAT).unwrap();
+        let due = |monthday, overflow, from: &str, to: &str| -> Vec<u32> {
+            day(from)
+                .iter_days()
+                .take_while(|d| *d <= day(to))
+                .filter(|d| day_check(monthday, overflow, *d))
+                .map(|d| d.month() * 100 + d.day())
+                .collect()
+        };
+        let (clamp, skip, roll) = (
+            MonthOverflow::Clamp,
+            MonthOverflow::Skip,
+            MonthOverflow::Roll,
+        );
+        assert_eq!(due(31, clamp, "20240101", "20240430"), [131, 229, 331, 430]);
+        assert_eq!(due(31, skip, "20240101", "20240430"), [131, 331]);
+        assert_eq!(due(31, roll, "20240101", "20240501"), [131, 301, 331, 501]);
+        assert_eq!(due(30, roll, "20240201", "20240331"), [301, 330]);
+        assert_eq!(due(-1, skip, "20240101", "20240331"), [131, 229, 331]);
+        assert_eq!(due(-2, skip, "20240201", "20240331"), [228, 330]);
+        assert_eq!(due(-31, skip, "20240201", "20240229"), [201]);
+        assert_eq!(days_in_month(day("20231215")), 31);
+    }
+
+    #[test]
+    fn test_checks_against_today() {
+        let today = NaiveDate::from_ymd_opt(2024, 4, 2).unwrap();
+        let now = today.and_hms_opt(14, 30, 0).unwrap();
+        let yesterday = today.pred_opt().unwrap();
+        let tomorrow = today.succ_opt().unwrap();
+        assert_eq!(date_check(today, None, None, now), OnceDateStatus::Ongoing);
+        assert_eq!(
+            date_check(yesterday, None, None, now),
+            OnceDateStatus::Expired
+        );
+        assert_eq!(
+            date_check(tomorrow, None, None, now),
+            OnceDateStatus::Upcoming
+        );
+        let at = |time| date_check(today, Some(parse_time_of_day(time).unwrap()), None, now);
+        assert_eq!(at("14:30"), OnceDateStatus::Ongoing);
+        assert_eq!(at("14:29"), OnceDateStatus::Expired);
+        assert_eq!(
+            date_check(tomorrow, NaiveTime::from_hms_opt(9, 0, 0), None, now),
+            OnceDateStatus::Upcoming
+        );
+        let in_three_days = today + Duration::days(3);
+        assert_eq!(
+            date_check(in_three_days, None, Some(3), now),
+            OnceDateStatus::DueSoon
+        );
+        assert_eq!(
+            date_check(in_three_days, None, Some(2), now),
+            OnceDateStatus::Upcoming
+        );
+        assert_eq!(
+            date_check(today, None, Some(3), now),
+            OnceDateStatus::Ongoing
+        );
+        assert!(weekday_check(&[Weekday::Mon, Weekday::Tue], today));
+        assert!(!weekday_check(&[Weekday::Wed], today));
+        assert!(!weekday_check(&[], today));
+        assert!(day_check(2, MonthOverflow::Skip, today));
+        assert!(!day_check(3, MonthOverflow::Skip, today));
+    }
+
+    #[test]
+    fn test_get_greeting() {
+        let greeting = Greeting::default();
+        let at = |hour| {
+            Clock::fixed(
+                NaiveDate::from_ymd_opt(2024, 4, 2)
+                    .unwrap()
+                    .and_hms_opt(hour, 0, 0)
+                    .unwrap(),
+            )
+        };
+        assert_eq!(get_greeting(&greeting, &at(9)), "Good morning!");
+        assert_eq!(get_greeting(&greeting, &at(12)), "Good afternoon!");
+        assert_eq!(get_greeting(&greeting, &at(20)), "Good evening!");
     }
 }
@@ -0,0 +1,209 @@
+use crate::task::{Task, TaskType};
+use colored::Colorize;
+use serde_json::Value;
+use std::{collections::HashSet, fmt::Display};
+use uuid::Uuid;
+
+/// Something wrong with one entry of the journal, found by `ttd doctor`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// Position of the entry in the journal, starting at 1.
+    pub position: usize,
+    pub kind: ProblemKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProblemKind {
+    /// The entry is not a task at all, with the reason serde gave.
+    Unreadable(String),
+    InvalidDay(i32),
+    DuplicateId(usize),
+    DuplicateUid(Uuid),
+}
+
+impl ProblemKind {
+    /// Duplicate ids and uids are fixed in place, the other problems make the
+    /// entry unusable and it is quarantined.
+    pub fn needs_quarantine(&self) -> bool {
+        !matches!(
+            self,
+            ProblemKind::DuplicateId(_) | ProblemKind::DuplicateUid(_)
+        )
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match &self.kind {
+            ProblemKind::Unreadable(reason) => format!("not a valid task ({})", reason),
+            ProblemKind::InvalidDay(day) => format!("invalid monthday {}", day),
+            ProblemKind::DuplicateId(id) => format!("duplicate index {}", id),
+            ProblemKind::DuplicateUid(uid) => format!("duplicate uid {}", uid),
+        };
+        let action = if self.kind.needs_quarantine() {
+            "quarantine".bright_red()
+        } else {
+            "fix".bright_yellow()
+        };
+        write!(
+            f,
+            "{}: {} - {}",
+            format!("entry {}", self.position).bright_blue(),
+            description,
+            action
+        )
+    }
+}
+
+/// Checks the fields of a task that serde accepts but the rest of ttd cannot use.
+/// Invalid weekdays, dates and times are already rejected by serde.
+pub fn check_task(task: &Task) -> Option<ProblemKind> {
+    match &task.content {
+        TaskType::MonthTask { day, .. } if *day == 0 || !(-31..=31).contains(day) => {
+            Some(ProblemKind::InvalidDay(*day))
+        }
+        _ => None,
+    }
+}
+
+/// Finds every problem in the entries of a journal.
+pub fn diagnose(entries: &[Value]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut ids = HashSet::new();
+    let mut uids = HashSet::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let position = index + 1;
+        let task = match serde_json::from_value::<Task>(entry.clone()) {
+            Ok(task) => task,
+            Err(e) => {
+                let reason = match entry {
+                    // Kept as text by a store that found no JSON in it.
+                    Value::String(raw) => match serde_json::from_str::<Value>(raw) {
+                        Err(e) => format!("not JSON: {}", e),
+                        Ok(_) => e.to_string(),
+                    },
+                    _ => e.to_string(),
+                };
+                let kind = ProblemKind::Unreadable(reason);
+                problems.push(Problem { position, kind });
+                continue;
+            }
+        };
+        if let Some(kind) = check_task(&task) {
+            problems.push(Problem { position, kind });
+            continue;
+        }
+        if !ids.insert(task.id) {
+            let kind = ProblemKind::DuplicateId(task.id);
+            problems.push(Problem { position, kind });
+        }
+        if !uids.insert(task.uid) {
+            let kind = ProblemKind::DuplicateUid(task.uid);
+            problems.push(Problem { position, kind });
+        }
+    }
+
+    problems
+}
+
+/// Splits the entries into the usable tasks, renumbered and with unique
+/// uids, and the entries to quarantine.
+pub fn repair(entries: Vec<Value>) -> (Vec<Task>, Vec<Value>) {
+    let mut tasks: Vec<Task> = Vec::new();
+    let mut quarantined = Vec::new();
+    let mut uids = HashSet::new();
+    for entry in entries {
+        match serde_json::from_value::<Task>(entry.clone()) {
+            Ok(task) if check_task(&task).is_none() => tasks.push(task),
+            _ => quarantined.push(entry),
+        }
+    }
+    tasks.iter_mut().enumerate().for_each(|(index, task)| {
+        task.id = index + 1;
+        while !uids.insert(task.uid) {
+            task.uid = Uuid::new_v4();
+        }
+    });
+
+    (tasks, quarantined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(id: usize, uid: &str, content: Value) -> Value {
+        json!({
+            "id": id,
+            "uid": uid,
+            "created_at": "2024-04-01 08:00:00",
+            "content": content,
+        })
+    }
+
+    #[test]
+    fn test_diagnose_and_repair() {
+        let uid = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let entries = vec![
+            entry(
+                1,
+                uid,
+                json!({"ProgressTask": {"text": "book", "progress": "page 1"}}),
+            ),
+            entry(
+                1,
+                uid,
+                json!({"WeekTask": {"text": "gym", "weekdays": ["Mon"]}}),
+            ),
+            entry(
+                3,
+                uid,
+                json!({"WeekTask": {"text": "gym", "weekdays": ["Mon", "Funday"]}}),
+            ),
+            entry(
+                4,
+                uid,
+                json!({"OnceTask": {"text": "trip", "date": "2024-13-01"}}),
+            ),
+            entry(5, uid, json!({"MonthTask": {"text": "rent", "day": 32}})),
+            json!({"id": 6, "content": "nothing"}),
+            entry(
+                7,
+                uid,
+                json!({"OnceTask": {"text": "call", "date": "20240401", "time": "9am"}}),
+            ),
+        ];
+        let problems = diagnose(&entries);
+        let kinds: Vec<_> = problems
+            .iter()
+            .map(|p| (p.position, p.kind.clone()))
+            .collect();
+        assert_eq!(kinds.len(), 7);
+        assert_eq!(kinds[0], (2, ProblemKind::DuplicateId(1)));
+        assert_eq!(
+            kinds[1],
+            (2, ProblemKind::DuplicateUid(uid.parse().unwrap()))
+        );
+        let unreadable = |kind: &ProblemKind, reason: &str| matches!(kind, ProblemKind::Unreadable(e) if e.contains(reason));
+        assert_eq!(kinds[2].0, 3);
+        assert!(unreadable(&kinds[2].1, "invalid weekday 'Funday'"));
+        assert_eq!(kinds[3].0, 4);
+        assert!(unreadable(&kinds[3].1, "invalid date '2024-13-01'"));
+        assert_eq!(kinds[4], (5, ProblemKind::InvalidDay(32)));
+        assert!(matches!(kinds[5], (6, ProblemKind::Unreadable(_))));
+        assert_eq!(kinds[6].0, 7);
+        assert!(unreadable(&kinds[6].1, "invalid time '9am'"));
+
+        let (tasks, quarantined) = repair(entries);
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(quarantined.len(), 5);
+        assert_eq!((tasks[0].id, tasks[1].id), (1, 2));
+        assert_ne!(tasks[0].uid, tasks[1].uid);
+        let entries: Vec<Value> = tasks
+            .iter()
+            .map(|t| serde_json::to_value(t).unwrap())
+            .collect();
+        assert!(diagnose(&entries).is_empty());
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{
+    date::{self, Clock},
+    task::Task,
+};
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+/// How many operations are kept before the oldest ones are dropped.
+const MAX_OPERATIONS: usize = 50;
+
+/// The changes made to a journal, used by `ttd undo`, `ttd redo` and `ttd history`.
+///
+/// Operations before `cursor` are applied and can be undone, the ones from
+/// `cursor` on have been undone and can be redone until a new change is made.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct History {
+    pub operations: Vec<Operation>,
+    pub cursor: usize,
+}
+
+/// One change to the journal, kept as the tasks it replaced and the ones that
+/// took their place, so the history stays small however long the list is.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "StoredOperation")]
+pub struct Operation {
+    pub description: String,
+    pub time: DateTime<Utc>,
+    /// Position of the first task that changed.
+    pub start: usize,
+    /// The tasks from `start` on before the change.
+    pub removed: Vec<Task>,
+    /// The tasks from `start` on after the change. The tasks after them are
+    /// the same as before, renumbered if the list changed length.
+    pub inserted: Vec<Task>,
+    /// Fingerprints of the whole list before and after the change, so the
+    /// operation is only applied to the journal it was recorded on.
+    pub checksum_before: u64,
+    pub checksum_after: u64,
+}
+
+/// The operations as read from the history file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredOperation {
+    Patch {
+        description: String,
+        #[serde(deserialize_with = "date::deserialize_timestamp")]
+        time: DateTime<Utc>,
+        start: usize,
+        removed: Vec<Task>,
+        inserted: Vec<Task>,
+        checksum_before: u64,
+        checksum_after: u64,
+    },
+    /// Written before operations were kept as patches, with the whole list
+    /// before and after the change.
+    Snapshots {
+        description: String,
+        #[serde(deserialize_with = "date::deserialize_timestamp")]
+        time: DateTime<Utc>,
+        before: Vec<Task>,
+        after: Vec<Task>,
+    },
+}
+
+impl From<StoredOperation> for Operation {
+    fn from(operation: StoredOperation) -> Operation {
+        match operation {
+            StoredOperation::Patch {
+                description,
+                time,
+                start,
+                removed,
+                inserted,
+                checksum_before,
+                checksum_after,
+            } => Operation {
+                description,
+                time,
+                start,
+                removed,
+                inserted,
+                checksum_before,
+                checksum_after,
+            },
+            StoredOperation::Snapshots {
+                description,
+                time,
+                before,
+                after,
+            } => Operation::new(description, time, &before, &after),
+        }
+    }
+}
+
+impl Operation {
+    /// The patch turning `before` into `after`: everything between their common
+    /// start and their common end, where the end may have been renumbered.
+    fn new(description: String, time: DateTime<Utc>, before: &[Task], after: &[Task]) -> Operation {
+        let start = before
+            .iter()
+            .zip(after)
+            .take_while(|(old, new)| old == new)
+            .count();
+        let shift = id_shift(before.len(), after.len());
+        let end = before[start..]
+            .iter()
+            .rev()
+            .zip(after[start..].iter().rev())
+            .take_while(|(old, new)| renumbered(old, shift) == **new)
+            .count();
+        Operation {
+            description,
+            time,
+            start,
+            removed: before[start..before.len() - end].to_vec(),
+            inserted: after[start..after.len() - end].to_vec(),
+            checksum_before: fingerprint(before),
+            checksum_after: fingerprint(after),
+        }
+    }
+
+    /// The list before the operation, rebuilt from `tasks`, the list after it.
+    pub fn revert(&self, tasks: &[Task]) -> anyhow::Result<Vec<Task>> {
+        check_unchanged(tasks, self.checksum_after)?;
+        patch(tasks, self.start, &self.inserted, &self.removed)
+    }
+
+    /// The list after the operation, rebuilt from `tasks`, the list before it.
+    pub fn replay(&self, tasks: &[Task]) -> anyhow::Result<Vec<Task>> {
+        check_unchanged(tasks, self.checksum_before)?;
+        patch(tasks, self.start, &self.removed, &self.inserted)
+    }
+}
+
+/// Fails unless `tasks` is the list the operation expects, e.g. after the
+/// journal was edited by hand or restored from a backup.
+fn check_unchanged(tasks: &[Task], checksum: u64) -> anyhow::Result<()> {
+    if fingerprint(tasks) != checksum {
+        return Err(anyhow!(
+            "{}",
+            "error: The journal has changed since this operation, it can't be applied!"
+                .bright_red()
+        ));
+    }
+
+    Ok(())
+}
+
+/// FNV-1a hash of the tasks as JSON, stable across runs unlike `DefaultHasher`.
+fn fingerprint(tasks: &[Task]) -> u64 {
+    serde_json::to_vec(tasks)
+        .unwrap_or_default()
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Replaces `from`, found at `start` in `tasks`, with `to` and renumbers the
+/// tasks after it.
+fn patch(tasks: &[Task], start: usize, from: &[Task], to: &[Task]) -> anyhow::Result<Vec<Task>> {
+    let end = start + from.len();
+    if tasks.get(start..end) != Some(from) {
+        return Err(anyhow!(
+            "{}",
+            "error: The journal has changed since this operation, it can't be applied!"
+                .bright_red()
+        ));
+    }
+    let shift = id_shift(from.len(), to.len());
+    let mut patched = tasks[..start].to_vec();
+    patched.extend_from_slice(to);
+    patched.extend(tasks[end..].iter().map(|task| renumbered(task, shift)));
+
+    Ok(patched)
+}
+
+/// How much the ids after a change move when it turns `from` tasks into `to`.
+fn id_shift(from: usize, to: usize) -> isize {
+    to as isize - from as isize
+}
+
+fn renumbered(task: &Task, shift: isize) -> Task {
+    Task {
+        id: task.id.wrapping_add_signed(shift),
+        ..task.clone()
+    }
+}
+
+impl History {
+    /// Records a change, stamped with the real time even when running with `--today`.
+    pub fn record(&mut self, description: &str, before: &[Task], after: &[Task]) {
+        self.operations.truncate(self.cursor);
+        self.operations.push(Operation::new(
+            description.to_string(),
+            Clock::system().timestamp(),
+            before,
+            after,
+        ));
+        if self.operations.len() > MAX_OPERATIONS {
+            self.operations
+                .drain(..self.operations.len() - MAX_OPERATIONS);
+        }
+        self.cursor = self.operations.len();
+    }
+
+    /// Steps back over the last applied operation.
+    pub fn undo(&mut self) -> Option<&Operation> {
+        self.cursor = self.cursor.checked_sub(1)?;
+        self.operations.get(self.cursor)
+    }
+
+    /// Steps forward over the last undone operation.
+    pub fn redo(&mut self) -> Option<&Operation> {
+        let operation = self.operations.get(self.cursor)?;
+        self.cursor += 1;
+        Some(operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::TaskType;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut history = History::default();
+        history.record("first", &[], &[]);
+        history.record("second", &[], &[]);
+        assert_eq!(history.undo().unwrap().description, "second");
+        assert_eq!(history.undo().unwrap().description, "first");
+        assert!(history.undo().is_none());
+        assert_eq!(history.redo().unwrap().description, "first");
+
+        history.record("third", &[], &[]);
+        assert!(history.redo().is_none());
+        assert_eq!(history.operations.len(), 2);
+        assert_eq!(history.undo().unwrap().description, "third");
+    }
+
+    #[test]
+    fn test_history_is_capped() {
+        let mut history = History::default();
+        (0..MAX_OPERATIONS + 5).for_each(|i| history.record(&i.to_string(), &[], &[]));
+        assert_eq!(history.operations.len(), MAX_OPERATIONS);
+        assert_eq!(history.operations[0].description, "5");
+        assert_eq!(history.cursor, MAX_OPERATIONS);
+    }
+
+    fn tasks(texts: &[&str]) -> Vec<Task> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| {
+                let mut task = Task::build(
+                    TaskType::ProgressTask {
+                        text: text.to_string(),
+                        progress: "page 1".to_string(),
+                    },
+                    &Clock::system(),
+                );
+                task.id = index + 1;
+                task
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_operation_is_a_patch() {
+        let before = tasks(&["a", "b", "c", "d", "e"]);
+        let mut after = before.clone();
+        after.remove(1);
+        after
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, task)| task.id = i + 1);
+        let mut history = History::default();
+        history.record("remove 2", &before, &after);
+        let operation = history.undo().unwrap();
+        assert_eq!(operation.start, 1);
+        assert_eq!(operation.removed, before[1..2]);
+        assert!(operation.inserted.is_empty());
+        assert_eq!(operation.revert(&after).unwrap(), before);
+        assert_eq!(operation.replay(&before).unwrap(), after);
+        assert!(operation.replay(&after).is_err());
+        // Same tasks at the patched position, but the rest of the list moved on.
+        let mut changed = after.clone();
+        changed[3].content = before[0].content.clone();
+        assert!(operation.revert(&changed).is_err());
+
+        let mut edited = after.clone();
+        edited[3].content = before[0].content.clone();
+        history.record("edit 4", &after, &edited);
+        let operation = history.undo().unwrap();
+        assert_eq!((operation.start, operation.removed.len()), (3, 1));
+        assert_eq!(operation.revert(&edited).unwrap(), after);
+    }
+
+    #[test]
+    fn test_read_snapshots() {
+        let after = tasks(&["a", "b", "c"]);
+        let before = after[..2].to_vec();
+        let stored = serde_json::json!({
+            "description": "add c",
+            "time": "2024-04-01T08:00:00Z",
+            "before": before,
+            "after": after,
+        });
+        let operation: Operation = serde_json::from_value(stored).unwrap();
+        assert_eq!((operation.start, operation.inserted.len()), (2, 1));
+        assert_eq!(operation.revert(&after).unwrap(), before);
+    }
+}
@@ -1,4 +1,13 @@
+pub mod backup;
 pub mod cli;
+pub mod config;
 pub mod date;
+pub mod doctor;
+pub mod history;
+pub mod rrule;
+pub mod schema;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 pub mod storage;
+pub mod store;
 pub mod task;
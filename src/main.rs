@@ -1,14 +1,61 @@
 use clap::Parser;
 use colored::Colorize;
-use ttd::{cli, storage};
+use std::{fs, process};
+use ttd::{
+    cli,
+    config::{self, Config},
+    date::{self, Clock},
+    storage,
+    store::{Backend, Journal, TaskFilter, TaskStore},
+};
 
 fn main() {
     let cli = cli::Cli::parse();
 
-    storage::init().unwrap();
+    let mut config = or_exit(Config::load());
+    if !config.color {
+        colored::control::set_override(false);
+    }
+    config.colors.clone().install();
+    let journal = match cli.journal.as_ref().or(config.journal.as_ref()) {
+        Some(journal) => {
+            let path = config.journal_path(journal);
+            let located = Journal::locate(&path);
+            // The journal was migrated since the path was chosen.
+            if located.path != path {
+                println!(
+                    "{}{}{}",
+                    "warning: The journal has been migrated, using ".bright_yellow(),
+                    located.path.display().to_string().bright_yellow(),
+                    " instead".bright_yellow()
+                );
+            }
+            located
+        }
+        None => or_exit(Journal::locate_default()),
+    };
+    let mut store: Box<dyn TaskStore> = or_exit(journal.open(config.backups));
+    // The doctor has to work on journals that the other commands refuse to read.
+    if let Some(cli::Commands::Doctor { fix }) = cli.command {
+        or_exit(storage::doctor(store.as_mut(), fix));
+        return;
+    }
+    let zone = match &cli.tz {
+        Some(zone) => or_exit(date::parse_zone(zone)),
+        None => config.zone(),
+    };
+    let clock = match &cli.today {
+        Some(today) => Clock::day(or_exit(date::parse_user_date(
+            today,
+            &config.date_format,
+            Clock::system().in_zone(zone).today(),
+        )))
+        .in_zone(zone),
+        None => Clock::system().in_zone(zone),
+    };
 
     if cli.path {
-        match storage::path_check() {
+        match storage::path_check(store.as_ref()) {
             Ok(path) => println!(
                 "{}{}",
                 "The path of journal file is: ".bright_green(),
@@ -17,6 +64,14 @@ fn main() {
             Err(e) => println!("{}", e),
         }
     }
+    // The config and the backups don't depend on the tasks, and restoring a
+    // backup is a way out of a broken journal.
+    if !matches!(
+        cli.command,
+        None | Some(cli::Commands::Config { .. }) | Some(cli::Commands::Backup { .. })
+    ) {
+        or_exit(storage::check_journal(store.as_ref()));
+    }
 
     match cli.command {
         Some(cli::Commands::Add {
@@ -25,17 +80,29 @@ fn main() {
             day,
             date,
             progress,
+            rule,
+            remind,
             multiple,
         }) => {
             if multiple {
-                match storage::handle_user_input() {
+                match storage::handle_user_input(store.as_mut(), &config, &clock) {
                     Ok(_) => {}
                     Err(e) => println!("{}", e),
                 }
             } else {
-                match storage::parse_task(text.unwrap(), weekday, day, date, progress) {
+                match storage::parse_task(
+                    text.unwrap(),
+                    weekday,
+                    day,
+                    date,
+                    progress,
+                    rule,
+                    remind,
+                    &config,
+                    &clock,
+                ) {
                     Ok(task) => {
-                        if let Err(e) = storage::add_task(task) {
+                        if let Err(e) = storage::add_task(store.as_mut(), task, &clock) {
                             println!("{}", e);
                         }
                     }
@@ -51,6 +118,8 @@ fn main() {
             month_task,
             week_task,
             progress_task,
+            recurring_task,
+            done,
         }) => {
             match (
                 id,
@@ -60,25 +129,30 @@ fn main() {
                 month_task,
                 week_task,
                 progress_task,
+                recurring_task,
+                done,
             ) {
-                (id, false, false, false, false, false, false) => {
-                    match storage::remove_task_by_id(id) {
+                (id, false, false, false, false, false, false, false, false) => {
+                    match storage::remove_task_by_id(store.as_mut(), id.as_deref(), &clock) {
                         Ok(_) => {}
                         Err(e) => println!("{}", e),
                     }
                 }
-                (_, true, ..) => match storage::clear_tasks() {
+                (_, true, ..) => match storage::clear_tasks(store.as_mut()) {
                     Ok(_) => {}
                     Err(e) => println!("{}", e),
                 },
                 _ => {
-                    match storage::remove_tasks_by_filter(
+                    let filter = TaskFilter::from_flags(
                         expired,
                         once_task,
                         month_task,
                         week_task,
                         progress_task,
-                    ) {
+                        recurring_task,
+                        done,
+                    );
+                    match storage::remove_tasks_by_filter(store.as_mut(), filter, &clock) {
                         Ok(_) => {}
                         Err(e) => println!("{}", e),
                     }
@@ -91,29 +165,169 @@ fn main() {
             month_task,
             week_task,
             progress_task,
+            recurring_task,
+            done,
         }) => {
-            match storage::list_tasks_by_filter(
+            let filter = match TaskFilter::from_flags(
                 expired,
                 once_task,
                 month_task,
                 week_task,
                 progress_task,
+                recurring_task,
+                done,
             ) {
+                TaskFilter::All => config.default_filter,
+                filter => filter,
+            };
+            match storage::list_tasks_by_filter(store.as_ref(), filter, &clock) {
                 Ok(_) => {}
                 Err(e) => println!("{}", e),
             }
         }
         Some(cli::Commands::Today) => {
-            if let Err(e) = storage::tasks_of_today() {
+            if let Err(e) = storage::tasks_of_today(store.as_ref(), &config, &clock) {
+                println!("{}", e);
+            }
+        }
+        Some(cli::Commands::Agenda { from, to }) => {
+            let result = storage::show_agenda(
+                store.as_ref(),
+                from.as_deref(),
+                to.as_deref(),
+                &config,
+                &clock,
+            );
+            if let Err(e) = result {
+                println!("{}", e);
+            }
+        }
+        Some(cli::Commands::Cal { month, list }) => {
+            if let Err(e) = storage::show_calendar(store.as_ref(), month.as_deref(), list, &clock) {
+                println!("{}", e);
+            }
+        }
+        Some(cli::Commands::Edit {
+            id,
+            text,
+            weekday,
+            day,
+            date,
+            progress,
+            rule,
+            remind,
+            editor,
+        }) => {
+            let result = if editor {
+                storage::edit_task_in_editor(store.as_mut(), &id, &config, &clock)
+            } else {
+                let edit = storage::TaskEdit {
+                    text,
+                    weekday,
+                    day,
+                    date,
+                    progress,
+                    rule,
+                    remind,
+                };
+                storage::edit_task(store.as_mut(), &id, edit, &config, &clock)
+            };
+            if let Err(e) = result {
+                println!("{}", e);
+            }
+        }
+        Some(cli::Commands::Done { id }) => {
+            if let Err(e) = storage::complete_task(store.as_mut(), &id, &clock) {
                 println!("{}", e);
             }
         }
         Some(cli::Commands::Update { id, new_progress }) => {
-            match storage::update_bookmark(id, new_progress) {
+            match storage::update_bookmark(store.as_mut(), &id, new_progress, &clock) {
                 Ok(_) => {}
                 Err(e) => println!("{}", e),
             }
         }
-        None => (),
+        Some(cli::Commands::Undo) => {
+            if let Err(e) = storage::undo(store.as_mut()) {
+                println!("{}", e);
+            }
+        }
+        Some(cli::Commands::Redo) => {
+            if let Err(e) = storage::redo(store.as_mut()) {
+                println!("{}", e);
+            }
+        }
+        Some(cli::Commands::History) => {
+            if let Err(e) = storage::show_history(store.as_ref(), &clock) {
+                println!("{}", e);
+            }
+        }
+        Some(cli::Commands::Migrate { to }) => {
+            if to == journal.backend {
+                println!(
+                    "{}",
+                    "warning: The journal is already stored in this backend!".bright_yellow()
+                );
+                return;
+            }
+            let result = journal
+                .with_backend(to)
+                .open(config.backups)
+                .and_then(|mut target| {
+                    storage::migrate(store.as_mut(), target.as_mut())?;
+                    // `Journal::locate` prefers SQLite whenever its file exists, so
+                    // moving back to JSON has to remove it.
+                    if journal.backend == Backend::Sqlite {
+                        fs::remove_file(&journal.path)?;
+                    }
+                    Ok(())
+                });
+            if let Err(e) = result {
+                println!("{}", e);
+            }
+        }
+        Some(cli::Commands::Backup { action }) => {
+            let result = match action {
+                cli::BackupAction::List => storage::list_backups(store.as_ref()),
+                cli::BackupAction::Restore { n } => storage::restore_backup(store.as_mut(), n),
+            };
+            if let Err(e) = result {
+                println!("{}", e);
+            }
+        }
+        Some(cli::Commands::Config { action }) => {
+            let result = match action {
+                cli::ConfigAction::Get { key } => {
+                    config.get(&key).map(|value| println!("{}", value))
+                }
+                cli::ConfigAction::Set { key, value } => config
+                    .set(&key, &value)
+                    .and_then(|_| config.save())
+                    .map(|_| println!("{} {} = {}", "Config updated:".bright_green(), key, value)),
+                cli::ConfigAction::List => config.entries().and_then(|entries| {
+                    println!(
+                        "{}{}",
+                        "The path of config file is: ".bright_green(),
+                        config::config_path()?.display().to_string().bright_green()
+                    );
+                    entries
+                        .into_iter()
+                        .for_each(|(key, value)| println!("{} = {}", key, value));
+                    Ok(())
+                }),
+            };
+            if let Err(e) = result {
+                println!("{}", e);
+            }
+        }
+        Some(cli::Commands::Doctor { .. }) | None => (),
     }
 }
+
+/// Prints the error and exits, for the failures that leave nothing to run.
+fn or_exit<T>(result: anyhow::Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        println!("{}", e);
+        process::exit(1)
+    })
+}
@@ -0,0 +1,641 @@
+use crate::date;
+use anyhow::anyhow;
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fmt::Display, str::FromStr};
+
+/// A recurrence rule, written like the RRULE of RFC 5545, e.g.
+/// `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH` or `FREQ=MONTHLY;BYDAY=-1FR`.
+///
+/// Supported parts are `FREQ`, `INTERVAL`, `BYDAY`, `BYMONTHDAY`, `BYMONTH`,
+/// `UNTIL` and `COUNT`, plus `DTSTART` for the first day the rule applies.
+/// Weeks start on Monday, and in yearly rules `BYDAY` and `BYMONTHDAY` apply
+/// within the months of `BYMONTH`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<ByDay>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+    pub start: NaiveDate,
+    pub until: Option<NaiveDate>,
+    pub count: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A weekday of `BYDAY`, optionally the n-th one of the month (`2MO`), or
+/// counted from its end (`-1FR`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByDay {
+    pub nth: Option<i32>,
+    pub weekday: Weekday,
+}
+
+/// How long a rule may go without an occurrence before it is considered
+/// exhausted, e.g. `FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30`. The calendar repeats
+/// every 400 years, so a rule that doesn't occur for that long never will.
+const MAX_GAP: Months = Months::new(400 * 12);
+
+impl Rule {
+    /// Parses a rule, starting on `today` unless it has a `DTSTART`.
+    pub fn parse(rule: &str, today: NaiveDate) -> anyhow::Result<Rule> {
+        let mut freq = None;
+        let mut parsed = Rule {
+            freq: Frequency::Daily,
+            interval: 1,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+            start: today,
+            until: None,
+            count: None,
+        };
+        for part in rule.split(';').filter(|part| !part.trim().is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(|| invalid(part))?;
+            let value = value.trim();
+            match key.trim().to_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(invalid(part)),
+                    })
+                }
+                "INTERVAL" => {
+                    parsed.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| *interval > 0)
+                        .ok_or_else(|| invalid(part))?
+                }
+                "BYDAY" => {
+                    parsed.by_day = value
+                        .split(',')
+                        .map(|day| parse_by_day(day).ok_or_else(|| invalid(part)))
+                        .collect::<anyhow::Result<_>>()?
+                }
+                "BYMONTHDAY" => {
+                    parsed.by_month_day =
+                        parse_list(value, |day: &i32| *day != 0 && (-31..=31).contains(day))
+                            .ok_or_else(|| invalid(part))?
+                }
+                "BYMONTH" => {
+                    parsed.by_month = parse_list(value, |month: &u32| (1..=12).contains(month))
+                        .ok_or_else(|| invalid(part))?
+                }
+                "DTSTART" => parsed.start = parse_day(value).ok_or_else(|| invalid(part))?,
+                "UNTIL" => parsed.until = Some(parse_day(value).ok_or_else(|| invalid(part))?),
+                "COUNT" => {
+                    parsed.count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|count| *count > 0)
+                            .ok_or_else(|| invalid(part))?,
+                    )
+                }
+                _ => return Err(invalid(part)),
+            }
+        }
+        parsed.freq = freq.ok_or_else(|| invalid("FREQ is missing"))?;
+        if parsed.until.is_some() && parsed.count.is_some() {
+            return Err(invalid("UNTIL and COUNT can't be used together"));
+        }
+        let has_nth = parsed.by_day.iter().any(|day| day.nth.is_some());
+        if has_nth && matches!(parsed.freq, Frequency::Daily | Frequency::Weekly) {
+            return Err(invalid(
+                "BYDAY with a number needs FREQ=MONTHLY or FREQ=YEARLY",
+            ));
+        }
+        if parsed.freq == Frequency::Weekly && !parsed.by_month_day.is_empty() {
+            return Err(invalid("BYMONTHDAY can't be used with FREQ=WEEKLY"));
+        }
+
+        Ok(parsed)
+    }
+
+    /// The days the task falls on, in order, from `start` on.
+    pub fn occurrences(&self) -> Occurrences<'_> {
+        Occurrences {
+            rule: self,
+            period: 0,
+            last: self.start,
+            pending: VecDeque::new(),
+            emitted: 0,
+        }
+    }
+
+    /// Whether the rule falls on `day`. Without a `COUNT` only the period
+    /// `day` is in is looked at, however long ago the rule started.
+    pub fn occurs_on(&self, day: NaiveDate) -> bool {
+        if self.count.is_some() {
+            return self
+                .occurrences()
+                .take_while(|occurrence| *occurrence <= day)
+                .any(|occurrence| occurrence == day);
+        }
+        if day < self.start || self.until.is_some_and(|until| day > until) {
+            return false;
+        }
+        self.period_of(day)
+            .and_then(|n| self.period(n))
+            .is_some_and(|days| days.contains(&day))
+    }
+
+    /// The latest occurrence on or before `day`. Without a `COUNT` the periods
+    /// are walked back from the one `day` is in.
+    pub fn last_on_or_before(&self, day: NaiveDate) -> Option<NaiveDate> {
+        if self.count.is_some() {
+            return self
+                .occurrences()
+                .take_while(|occurrence| *occurrence <= day)
+                .last();
+        }
+        let day = self.until.map_or(day, |until| day.min(until));
+        let gap_start = day.checked_sub_months(MAX_GAP).unwrap_or(NaiveDate::MIN);
+        (0..=self.period_of(day)?)
+            .rev()
+            .take_while(|n| {
+                self.period_start(*n)
+                    .is_some_and(|first| first >= gap_start)
+            })
+            .find_map(|n| {
+                self.period(n)?
+                    .into_iter()
+                    .rev()
+                    .find(|occurrence| *occurrence >= self.start && *occurrence <= day)
+            })
+    }
+
+    /// The first day of the `n`-th period after the one `start` is in.
+    fn period_start(&self, n: u32) -> Option<NaiveDate> {
+        let step = n.checked_mul(self.interval)?;
+        match self.freq {
+            Frequency::Daily => self.start.checked_add_signed(Duration::days(step.into())),
+            Frequency::Weekly => self
+                .start
+                .week(Weekday::Mon)
+                .first_day()
+                .checked_add_signed(Duration::weeks(step.into())),
+            Frequency::Monthly => first_of_month(self.start).checked_add_months(Months::new(step)),
+            Frequency::Yearly => {
+                let year = self.start.year().checked_add(i32::try_from(step).ok()?)?;
+                NaiveDate::from_ymd_opt(year, 1, 1)
+            }
+        }
+    }
+
+    /// The last period that starts on or before `day`, which is the one `day`
+    /// is in unless it falls between two periods of an `INTERVAL`.
+    fn period_of(&self, day: NaiveDate) -> Option<u32> {
+        let elapsed = match self.freq {
+            Frequency::Daily => (day - self.start).num_days(),
+            Frequency::Weekly => (day.week(Weekday::Mon).first_day()
+                - self.start.week(Weekday::Mon).first_day())
+            .num_weeks(),
+            Frequency::Monthly => months_between(self.start, day),
+            Frequency::Yearly => i64::from(day.year() - self.start.year()),
+        };
+        u32::try_from(elapsed / i64::from(self.interval)).ok()
+    }
+
+    /// The candidate days of the `n`-th period after the one `start` is in.
+    fn period(&self, n: u32) -> Option<Vec<NaiveDate>> {
+        let first = self.period_start(n)?;
+        let mut days = match self.freq {
+            Frequency::Daily => vec![first],
+            Frequency::Weekly => {
+                let monday = first;
+                let weekdays: Vec<Weekday> = match &self.by_day[..] {
+                    [] => vec![self.start.weekday()],
+                    by_day => by_day.iter().map(|day| day.weekday).collect(),
+                };
+                monday
+                    .iter_days()
+                    .take(7)
+                    .filter(|day| weekdays.contains(&day.weekday()))
+                    .collect()
+            }
+            Frequency::Monthly => self.days_in_month(first),
+            Frequency::Yearly => {
+                let year = first.year();
+                let months = match &self.by_month[..] {
+                    [] => vec![self.start.month()],
+                    months => months.to_vec(),
+                };
+                months
+                    .into_iter()
+                    .filter_map(|month| NaiveDate::from_ymd_opt(year, month, 1))
+                    .flat_map(|first| self.days_in_month(first))
+                    .collect()
+            }
+        };
+        days.retain(|day| self.by_month.is_empty() || self.by_month.contains(&day.month()));
+        if self.freq == Frequency::Daily {
+            days.retain(|day| {
+                (self.by_day.is_empty() || self.by_day.iter().any(|by| by.weekday == day.weekday()))
+                    && (self.by_month_day.is_empty() || matches_month_day(&self.by_month_day, *day))
+            });
+        }
+        days.sort();
+        days.dedup();
+
+        Some(days)
+    }
+
+    /// The candidate days of the month starting on `first`, for monthly and yearly rules.
+    fn days_in_month(&self, first: NaiveDate) -> Vec<NaiveDate> {
+        let days: Vec<NaiveDate> = first
+            .iter_days()
+            .take_while(|day| day.month() == first.month())
+            .collect();
+        if !self.by_month_day.is_empty() {
+            return days
+                .into_iter()
+                .filter(|day| matches_month_day(&self.by_month_day, *day))
+                .filter(|day| {
+                    self.by_day.is_empty()
+                        || self.by_day.iter().any(|by| by.weekday == day.weekday())
+                })
+                .collect();
+        }
+        if self.by_day.is_empty() {
+            return days
+                .into_iter()
+                .filter(|day| day.day() == self.start.day())
+                .collect();
+        }
+        self.by_day
+            .iter()
+            .flat_map(|by| {
+                let matching: Vec<NaiveDate> = days
+                    .iter()
+                    .copied()
+                    .filter(|day| day.weekday() == by.weekday)
+                    .collect();
+                match by.nth {
+                    None => matching,
+                    Some(nth) if nth > 0 => matching
+                        .get(nth as usize - 1)
+                        .copied()
+                        .into_iter()
+                        .collect(),
+                    Some(nth) => matching
+                        .len()
+                        .checked_sub(nth.unsigned_abs() as usize)
+                        .and_then(|index| matching.get(index).copied())
+                        .into_iter()
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Iterator over the occurrences of a [`Rule`], see [`Rule::occurrences`].
+pub struct Occurrences<'a> {
+    rule: &'a Rule,
+    period: u32,
+    /// The last occurrence, or `start` before the first one.
+    last: NaiveDate,
+    pending: VecDeque<NaiveDate>,
+    emitted: u32,
+}
+
+impl Iterator for Occurrences<'_> {
+    type Item = NaiveDate;
+
+    fn next(&mut self) -> Option<NaiveDate> {
+        if self.rule.count.is_some_and(|count| self.emitted >= count) {
+            return None;
+        }
+        while self.pending.is_empty() {
+            let first = self.rule.period_start(self.period)?;
+            if self
+                .last
+                .checked_add_months(MAX_GAP)
+                .is_none_or(|end| first > end)
+            {
+                return None;
+            }
+            let days = self.rule.period(self.period)?;
+            self.period += 1;
+            self.pending = days
+                .into_iter()
+                .filter(|day| *day >= self.rule.start)
+                .collect();
+        }
+        let day = self.pending.pop_front()?;
+        if self.rule.until.is_some_and(|until| day > until) {
+            // Every later day is past `until` as well.
+            self.pending.clear();
+            return None;
+        }
+        self.emitted += 1;
+        self.last = day;
+
+        Some(day)
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let freq = match self.freq {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        let mut parts = vec![format!("FREQ={}", freq)];
+        if self.interval != 1 {
+            parts.push(format!("INTERVAL={}", self.interval));
+        }
+        if !self.by_day.is_empty() {
+            parts.push(format!("BYDAY={}", join(&self.by_day)));
+        }
+        if !self.by_month_day.is_empty() {
+            parts.push(format!("BYMONTHDAY={}", join(&self.by_month_day)));
+        }
+        if !self.by_month.is_empty() {
+            parts.push(format!("BYMONTH={}", join(&self.by_month)));
+        }
+        parts.push(format!("DTSTART={}", self.start.format(date::DATE_FORMAT)));
+        if let Some(until) = self.until {
+            parts.push(format!("UNTIL={}", until.format(date::DATE_FORMAT)));
+        }
+        if let Some(count) = self.count {
+            parts.push(format!("COUNT={}", count));
+        }
+        write!(f, "{}", parts.join(";"))
+    }
+}
+
+impl Display for ByDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = &self.weekday.to_string().to_uppercase()[..2];
+        match self.nth {
+            Some(nth) => write!(f, "{}{}", nth, code),
+            None => write!(f, "{}", code),
+        }
+    }
+}
+
+/// Rules are stored in the journal in their text form, which always has a `DTSTART`.
+impl TryFrom<String> for Rule {
+    type Error = anyhow::Error;
+
+    fn try_from(rule: String) -> anyhow::Result<Rule> {
+        if !rule.to_uppercase().contains("DTSTART=") {
+            return Err(invalid("DTSTART is missing"));
+        }
+        Rule::parse(&rule, NaiveDate::MIN)
+    }
+}
+
+impl From<Rule> for String {
+    fn from(rule: Rule) -> String {
+        rule.to_string()
+    }
+}
+
+fn parse_by_day(day: &str) -> Option<ByDay> {
+    let day = day.trim();
+    let split = day.len().checked_sub(2)?;
+    let (nth, code) = (day.get(..split)?, day.get(split..)?);
+    let weekday = match code.to_uppercase().as_str() {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    };
+    let nth = match nth {
+        "" => None,
+        nth => Some(
+            nth.parse::<i32>()
+                .ok()
+                .filter(|n| *n != 0 && n.abs() <= 5)?,
+        ),
+    };
+
+    Some(ByDay { nth, weekday })
+}
+
+fn parse_list<T: FromStr>(value: &str, valid: impl Fn(&T) -> bool) -> Option<Vec<T>> {
+    value
+        .split(',')
+        .map(|item| item.trim().parse().ok().filter(&valid))
+        .collect()
+}
+
+fn parse_day(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, date::DATE_FORMAT).ok()
+}
+
+/// Whether `day` is one of `month_days`, where negative days count from the end of the month.
+fn matches_month_day(month_days: &[i32], day: NaiveDate) -> bool {
+    let days_in_month = date::days_in_month(day) as i32;
+    let (from_start, from_end) = (day.day() as i32, day.day() as i32 - days_in_month - 1);
+    month_days.contains(&from_start) || month_days.contains(&from_end)
+}
+
+/// How many month boundaries there are from `from` to `to`.
+fn months_between(from: NaiveDate, to: NaiveDate) -> i64 {
+    i64::from(to.year() - from.year()) * 12 + i64::from(to.month()) - i64::from(from.month())
+}
+
+fn first_of_month(day: NaiveDate) -> NaiveDate {
+    day - Duration::days(day.day0().into())
+}
+
+fn join<T: Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn invalid(reason: &str) -> anyhow::Error {
+    anyhow!(
+        "{}{}",
+        "error: Invalid repeat rule, expected e.g. FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH: "
+            .bright_red(),
+        reason.bright_red()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: &str) -> NaiveDate {
+        parse_day(date).unwrap()
+    }
+
+    fn first(rule: &str, n: usize) -> Vec<String> {
+        Rule::parse(rule, day("20240401"))
+            .unwrap()
+            .occurrences()
+            .take(n)
+            .map(|d| d.format("%Y%m%d").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let rule = Rule::parse("freq=weekly;interval=2;byday=mo,th", day("20240401")).unwrap();
+        assert_eq!(rule.freq, Frequency::Weekly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(
+            rule.to_string(),
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;DTSTART=20240401"
+        );
+        assert_eq!(Rule::try_from(rule.to_string()).unwrap(), rule);
+        let rule = Rule::parse("FREQ=MONTHLY;BYDAY=-1FR;COUNT=3", day("20240401")).unwrap();
+        assert_eq!(
+            rule.by_day[0],
+            ByDay {
+                nth: Some(-1),
+                weekday: Weekday::Fri
+            }
+        );
+
+        assert!(Rule::parse("INTERVAL=2", day("20240401")).is_err());
+        assert!(Rule::parse("FREQ=HOURLY", day("20240401")).is_err());
+        assert!(Rule::parse("FREQ=DAILY;INTERVAL=0", day("20240401")).is_err());
+        assert!(Rule::parse("FREQ=WEEKLY;BYDAY=1MO", day("20240401")).is_err());
+        assert!(Rule::parse("FREQ=MONTHLY;BYMONTHDAY=32", day("20240401")).is_err());
+        assert!(Rule::parse("FREQ=DAILY;COUNT=2;UNTIL=20240501", day("20240401")).is_err());
+        assert!(Rule::parse("FREQ=DAILY;SOMETIMES=1", day("20240401")).is_err());
+        assert!(Rule::try_from("FREQ=DAILY".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_every_other_week_on_mon_and_thu() {
+        assert_eq!(
+            first("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH", 4),
+            ["20240401", "20240404", "20240415", "20240418"]
+        );
+    }
+
+    #[test]
+    fn test_last_friday_of_the_month() {
+        assert_eq!(
+            first("FREQ=MONTHLY;BYDAY=-1FR", 3),
+            ["20240426", "20240531", "20240628"]
+        );
+        assert_eq!(
+            first("FREQ=MONTHLY;BYDAY=2MO,2WE", 2),
+            ["20240408", "20240410"]
+        );
+    }
+
+    #[test]
+    fn test_every_three_days() {
+        assert_eq!(
+            first("FREQ=DAILY;INTERVAL=3;DTSTART=20240430", 3),
+            ["20240430", "20240503", "20240506"]
+        );
+    }
+
+    #[test]
+    fn test_yearly_and_month_days() {
+        assert_eq!(
+            first("FREQ=YEARLY;BYMONTH=4;BYMONTHDAY=15", 2),
+            ["20240415", "20250415"]
+        );
+        assert_eq!(
+            first("FREQ=MONTHLY;BYMONTHDAY=-1", 3),
+            ["20240430", "20240531", "20240630"]
+        );
+        assert_eq!(
+            first("FREQ=MONTHLY;DTSTART=20240131", 3),
+            ["20240131", "20240331", "20240531"]
+        );
+        assert!(first("FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30", 1).is_empty());
+    }
+
+    #[test]
+    fn test_until_and_count() {
+        assert_eq!(first("FREQ=DAILY;COUNT=2", 5), ["20240401", "20240402"]);
+        assert_eq!(
+            first("FREQ=WEEKLY;UNTIL=20240415", 5),
+            ["20240401", "20240408", "20240415"]
+        );
+        let rule = Rule::parse("FREQ=WEEKLY;BYDAY=FR", day("20240401")).unwrap();
+        assert!(rule.occurs_on(day("20240412")));
+        assert!(!rule.occurs_on(day("20240411")));
+        assert!(!rule.occurs_on(day("20240329")));
+        assert_eq!(
+            rule.last_on_or_before(day("20240411")),
+            Some(day("20240405"))
+        );
+        assert_eq!(rule.last_on_or_before(day("20240404")), None);
+    }
+
+    #[test]
+    fn test_rare_daily_rule() {
+        let rule = Rule::parse("FREQ=DAILY;BYMONTH=2;BYMONTHDAY=29", day("20240401")).unwrap();
+        assert!(rule.occurs_on(day("20280229")));
+        assert!(rule.occurs_on(day("20320229")));
+        assert_eq!(rule.occurrences().nth(3), Some(day("20400229")));
+        assert_eq!(
+            rule.last_on_or_before(day("20350101")),
+            Some(day("20320229"))
+        );
+    }
+
+    #[test]
+    fn test_jump_to_period() {
+        let rules = [
+            "FREQ=DAILY;INTERVAL=3",
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH;DTSTART=20240403",
+            "FREQ=MONTHLY;BYDAY=-1FR",
+            "FREQ=MONTHLY;INTERVAL=5;DTSTART=20240131",
+            "FREQ=YEARLY;INTERVAL=2;BYMONTH=2,8;BYDAY=1SU",
+            "FREQ=WEEKLY;UNTIL=20240601",
+        ];
+        for rule in rules {
+            let rule = Rule::parse(rule, day("20240401")).unwrap();
+            let occurrences: Vec<NaiveDate> = rule
+                .occurrences()
+                .take_while(|d| *d < day("20270101"))
+                .collect();
+            for d in day("20240301")
+                .iter_days()
+                .take_while(|d| *d < day("20270101"))
+            {
+                assert_eq!(
+                    rule.occurs_on(d),
+                    occurrences.contains(&d),
+                    "{} {}",
+                    rule,
+                    d
+                );
+                assert_eq!(
+                    rule.last_on_or_before(d),
+                    occurrences.iter().rev().find(|o| **o <= d).copied(),
+                    "{} {}",
+                    rule,
+                    d
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use crate::{date, task::Task};
+use anyhow::anyhow;
+use chrono::SecondsFormat;
+use chrono_tz::Tz;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt::Display;
+use uuid::Uuid;
+
+/// Upgrades a journal from the version at its index to the next one.
+type Migration = fn(Value) -> anyhow::Result<Value>;
+
+/// The migration chain. A journal written by an older ttd goes through every
+/// step from its version on, so a new step only has to know about the
+/// version right before it.
+///
+/// The steps know nothing of the config, so the zone-less times of versions
+/// 3 and 4 are read on the wall clock of the system zone, not of the
+/// `timezone` setting or `--tz`.
+const MIGRATIONS: &[Migration] = &[
+    // 0 -> 1: wrap the bare task array in an envelope and give every task a uid.
+    wrap_in_envelope,
+    // 1 -> 2: drop `ongoing` and `status`, which are now worked out from the date.
+    drop_derived_status,
+    // 2 -> 3: week tasks repeat on a list of `weekdays` instead of a single `weekday`.
+    weekday_to_weekdays,
+    // 3 -> 4: timestamps are stored in UTC instead of on the wall clock of the system.
+    timestamps_to_utc,
+    // 4 -> 5: once-time tasks with a time are due on the wall clock of a zone.
+    once_times_in_zone,
+];
+
+/// The version of the journals written by this ttd.
+pub const VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// The layout of a journal file: the tasks next to the version of their format.
+#[derive(Serialize, Deserialize, Debug)]
+struct Envelope<T> {
+    version: u32,
+    tasks: T,
+}
+
+/// Serializes `tasks` as a journal of the current version.
+pub fn to_json(tasks: &[Task]) -> anyhow::Result<Vec<u8>> {
+    Ok(serde_json::to_vec_pretty(&Envelope {
+        version: VERSION,
+        tasks,
+    })?)
+}
+
+/// Reads a journal of any version, upgrading it to the current one first.
+pub fn from_json(content: &[u8]) -> anyhow::Result<Vec<Task>> {
+    from_value(serde_json::from_slice(content)?)
+}
+
+pub fn from_value(journal: Value) -> anyhow::Result<Vec<Task>> {
+    upgrade(journal)?
+        .into_iter()
+        .enumerate()
+        .map(|(index, task)| serde_json::from_value(task).map_err(|e| invalid_entry(index + 1, e)))
+        .collect()
+}
+
+/// The error for the entry at `position` (starting at 1) that is not a task,
+/// numbered like the entries `ttd doctor` reports.
+pub fn invalid_entry(position: usize, e: impl Display) -> anyhow::Error {
+    anyhow!(
+        "{}{}{}{}",
+        "error: Entry ".bright_red(),
+        position.to_string().bright_red(),
+        " of the journal is not a valid task: ".bright_red(),
+        e
+    )
+}
+
+/// Runs a journal of any version through the migrations and returns its
+/// entries without deserializing them, so broken ones can still be inspected.
+pub fn upgrade(journal: Value) -> anyhow::Result<Vec<Value>> {
+    let version = version_of(&journal)?;
+    let journal = MIGRATIONS[version as usize..]
+        .iter()
+        .try_fold(journal, |journal, migration| migration(journal))?;
+    let envelope: Envelope<Vec<Value>> = serde_json::from_value(journal).map_err(|e| {
+        anyhow!(
+            "{}{}",
+            "error: The journal is not a valid task list: ".bright_red(),
+            e
+        )
+    })?;
+    debug_assert_eq!(
+        envelope.version, VERSION,
+        "a migration did not bump the version"
+    );
+
+    Ok(envelope.tasks)
+}
+
+/// Journals written before the envelope was introduced are bare arrays, which
+/// count as version 0.
+pub fn version_of(journal: &Value) -> anyhow::Result<u32> {
+    let version = match journal {
+        Value::Array(_) => 0,
+        Value::Object(envelope) => envelope
+            .get("version")
+            .and_then(Value::as_u64)
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or(anyhow!(
+                "{}",
+                "error: The journal has no valid version!".bright_red()
+            ))?,
+        _ => {
+            return Err(anyhow!(
+                "{}",
+                "error: The journal is neither a task list nor a versioned journal!".bright_red()
+            ))
+        }
+    };
+    if version > VERSION {
+        return Err(anyhow!(
+            "{}{}{}{}",
+            "error: The journal was written by a newer ttd! journal version: ".bright_red(),
+            version.to_string().bright_red(),
+            ", supported version: ".bright_red(),
+            VERSION.to_string().bright_red()
+        ));
+    }
+
+    Ok(version)
+}
+
+fn wrap_in_envelope(journal: Value) -> anyhow::Result<Value> {
+    let Value::Array(mut tasks) = journal else {
+        return Err(anyhow!("{}", "error: Expected a task list!".bright_red()));
+    };
+    tasks
+        .iter_mut()
+        .filter_map(Value::as_object_mut)
+        .filter(|task| !task.contains_key("uid"))
+        .for_each(|task| {
+            task.insert("uid".to_string(), Uuid::new_v4().to_string().into());
+        });
+    let mut envelope = Map::new();
+    envelope.insert("version".to_string(), 1.into());
+    envelope.insert("tasks".to_string(), tasks.into());
+
+    Ok(envelope.into())
+}
+
+fn drop_derived_status(mut journal: Value) -> anyhow::Result<Value> {
+    let tasks = journal
+        .get_mut("tasks")
+        .and_then(Value::as_array_mut)
+        .ok_or(anyhow!("{}", "error: Expected a task list!".bright_red()))?;
+    tasks
+        .iter_mut()
+        .filter_map(|task| task.get_mut("content")?.as_object_mut())
+        .flat_map(|content| content.values_mut())
+        .filter_map(Value::as_object_mut)
+        .for_each(|fields| {
+            fields.remove("ongoing");
+            fields.remove("status");
+        });
+    journal["version"] = 2.into();
+
+    Ok(journal)
+}
+
+fn weekday_to_weekdays(mut journal: Value) -> anyhow::Result<Value> {
+    let tasks = journal
+        .get_mut("tasks")
+        .and_then(Value::as_array_mut)
+        .ok_or(anyhow!("{}", "error: Expected a task list!".bright_red()))?;
+    tasks
+        .iter_mut()
+        .filter_map(|task| {
+            task.get_mut("content")?
+                .get_mut("WeekTask")?
+                .as_object_mut()
+        })
+        .for_each(|fields| {
+            if let Some(weekday) = fields.remove("weekday") {
+                fields.insert("weekdays".to_string(), vec![weekday].into());
+            }
+        });
+    journal["version"] = 3.into();
+
+    Ok(journal)
+}
+
+/// The timestamps of version 3 were written on the wall clock of the system.
+fn timestamps_to_utc(journal: Value) -> anyhow::Result<Value> {
+    timestamps_from_zone(journal, date::system_zone())
+}
+
+/// Rewrites the zone-less timestamps written on the wall clock of `zone` as UTC.
+fn timestamps_from_zone(mut journal: Value, zone: Tz) -> anyhow::Result<Value> {
+    let tasks = journal
+        .get_mut("tasks")
+        .and_then(Value::as_array_mut)
+        .ok_or(anyhow!("{}", "error: Expected a task list!".bright_red()))?;
+    tasks
+        .iter_mut()
+        .filter_map(Value::as_object_mut)
+        .flat_map(|task| task.iter_mut())
+        .filter(|(key, _)| *key == "created_at" || *key == "completed_at")
+        .for_each(|(_, time)| {
+            if let Some(utc) = time
+                .as_str()
+                .and_then(|t| date::parse_timestamp(t, || zone))
+            {
+                *time = utc.to_rfc3339_opts(SecondsFormat::Secs, true).into();
+            }
+        });
+    journal["version"] = 4.into();
+
+    Ok(journal)
+}
+
+/// The times of once-time tasks in version 4 were on the wall clock of the
+/// display zone, which is taken to be the system zone even when `timezone`
+/// was set.
+fn once_times_in_zone(journal: Value) -> anyhow::Result<Value> {
+    once_times_from_zone(journal, date::system_zone())
+}
+
+/// Gives the once-time tasks with a time the zone whose wall clock they were read on.
+fn once_times_from_zone(mut journal: Value, zone: Tz) -> anyhow::Result<Value> {
+    let tasks = journal
+        .get_mut("tasks")
+        .and_then(Value::as_array_mut)
+        .ok_or(anyhow!("{}", "error: Expected a task list!".bright_red()))?;
+    tasks
+        .iter_mut()
+        .filter_map(|task| {
+            task.get_mut("content")?
+                .get_mut("OnceTask")?
+                .as_object_mut()
+        })
+        .filter(|fields| fields.get("time").is_some_and(|time| !time.is_null()))
+        .for_each(|fields| {
+            fields.entry("zone").or_insert_with(|| zone.name().into());
+        });
+    journal["version"] = 5.into();
+
+    Ok(journal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{date::Clock, task::TaskType};
+    use serde_json::json;
+
+    #[test]
+    fn test_wrap_in_envelope() {
+        let journal = json!([
+            {
+                "id": 1,
+                "created_at": "2024-04-01 08:00:00",
+                "content": {"ProgressTask": {"text": "book", "progress": "page 1"}}
+            },
+            {
+                "id": 2,
+                "uid": "67e55044-10b1-426f-9247-bb680e5fe0c8",
+                "created_at": "2024-04-01 08:00:00",
+                "content": {"ProgressTask": {"text": "notes", "progress": "page 2"}}
+            }
+        ]);
+        let journal = wrap_in_envelope(journal).unwrap();
+        assert_eq!(journal["version"], 1);
+        assert!(journal["tasks"][0]["uid"].is_string());
+        assert_eq!(
+            journal["tasks"][1]["uid"],
+            "67e55044-10b1-426f-9247-bb680e5fe0c8"
+        );
+        assert!(wrap_in_envelope(journal).is_err());
+    }
+
+    #[test]
+    fn test_drop_derived_status() {
+        let journal = json!({
+            "version": 1,
+            "tasks": [
+                {"content": {"WeekTask": {"text": "gym", "weekday": "Mon", "ongoing": true}}},
+                {"content": {"OnceTask": {"text": "trip", "date": "20240402", "status": "Expired"}}},
+                {"content": {"ProgressTask": {"text": "book", "progress": "page 1"}}}
+            ]
+        });
+        let journal = drop_derived_status(journal).unwrap();
+        assert_eq!(journal["version"], 2);
+        assert_eq!(
+            journal["tasks"][0]["content"],
+            json!({"WeekTask": {"text": "gym", "weekday": "Mon"}})
+        );
+        assert_eq!(
+            journal["tasks"][1]["content"],
+            json!({"OnceTask": {"text": "trip", "date": "20240402"}})
+        );
+        assert_eq!(
+            journal["tasks"][2]["content"],
+            json!({"ProgressTask": {"text": "book", "progress": "page 1"}})
+        );
+        assert!(drop_derived_status(json!([])).is_err());
+    }
+
+    #[test]
+    fn test_weekday_to_weekdays() {
+        let journal = json!({
+            "version": 2,
+            "tasks": [
+                {"content": {"WeekTask": {"text": "gym", "weekday": "Mon"}}},
+                {"content": {"ProgressTask": {"text": "book", "progress": "page 1"}}}
+            ]
+        });
+        let journal = weekday_to_weekdays(journal).unwrap();
+        assert_eq!(journal["version"], 3);
+        assert_eq!(
+            journal["tasks"][0]["content"],
+            json!({"WeekTask": {"text": "gym", "weekdays": ["Mon"]}})
+        );
+        assert_eq!(
+            journal["tasks"][1]["content"],
+            json!({"ProgressTask": {"text": "book", "progress": "page 1"}})
+        );
+        assert!(weekday_to_weekdays(json!([])).is_err());
+    }
+
+    #[test]
+    fn test_timestamps_to_utc() {
+        let journal = json!({
+            "version": 3,
+            "tasks": [
+                {"created_at": "2024-04-01 08:00:00", "completed_at": "2024-04-02 23:30:00"},
+                {"created_at": "2024-01-15 08:00:00"},
+                {"created_at": "2024-04-01T06:00:00Z"}
+            ]
+        });
+        let journal = timestamps_from_zone(journal, Tz::Europe__Paris).unwrap();
+        assert_eq!(journal["version"], 4);
+        assert_eq!(journal["tasks"][0]["created_at"], "2024-04-01T06:00:00Z");
+        assert_eq!(journal["tasks"][0]["completed_at"], "2024-04-02T21:30:00Z");
+        assert_eq!(journal["tasks"][1]["created_at"], "2024-01-15T07:00:00Z");
+        assert_eq!(journal["tasks"][2]["created_at"], "2024-04-01T06:00:00Z");
+        assert!(timestamps_to_utc(json!([])).is_err());
+
+        // The migration reads them in the system zone, whatever the config says.
+        let journal = json!({"version": 3, "tasks": [{"created_at": "2024-04-01 08:00:00"}]});
+        let expected = date::parse_timestamp("2024-04-01 08:00:00", date::system_zone).unwrap();
+        assert_eq!(
+            timestamps_to_utc(journal).unwrap()["tasks"][0]["created_at"],
+            expected.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+    }
+
+    #[test]
+    fn test_once_times_in_zone() {
+        let journal = json!({
+            "version": 4,
+            "tasks": [
+                {"content": {"OnceTask": {"text": "call", "date": "20240402", "time": "14:00"}}},
+                {"content": {"OnceTask": {"text": "trip", "date": "20240402"}}},
+                {"content": {"WeekTask": {"text": "gym", "weekdays": ["Mon"], "time": "09:00"}}}
+            ]
+        });
+        let journal = once_times_from_zone(journal, Tz::Asia__Tokyo).unwrap();
+        assert_eq!(journal["version"], 5);
+        assert_eq!(
+            journal["tasks"][0]["content"]["OnceTask"]["zone"],
+            "Asia/Tokyo"
+        );
+        assert!(journal["tasks"][1]["content"]["OnceTask"]
+            .get("zone")
+            .is_none());
+        assert!(journal["tasks"][2]["content"]["WeekTask"]
+            .get("zone")
+            .is_none());
+        assert!(once_times_in_zone(json!([])).is_err());
+
+        let journal = json!({
+            "version": 4,
+            "tasks": [{"content": {"OnceTask": {"text": "call", "date": "20240402", "time": "14:00"}}}]
+        });
+        assert_eq!(
+            once_times_in_zone(journal).unwrap()["tasks"][0]["content"]["OnceTask"]["zone"],
+            date::system_zone().name()
+        );
+    }
+
+    #[test]
+    fn test_typed_fields() {
+        let journal = json!({
+            "version": VERSION,
+            "tasks": [
+                {
+                    "id": 1,
+                    "created_at": "2024-04-01T08:00:00Z",
+                    "content": {"WeekTask": {"text": "gym", "weekdays": ["mon", "Friday"], "time": "09:00"}}
+                },
+                {
+                    "id": 2,
+                    "created_at": "2024-04-01T08:00:00Z",
+                    "content": {"OnceTask": {"text": "trip", "date": "2024-04-02"}}
+                }
+            ]
+        });
+        let tasks = from_value(journal).unwrap();
+        assert_eq!(
+            serde_json::to_value(&tasks[0].content).unwrap(),
+            json!({"WeekTask": {"text": "gym", "weekdays": ["Mon", "Fri"], "time": "09:00"}})
+        );
+        assert_eq!(
+            serde_json::to_value(&tasks[1].content).unwrap(),
+            json!({"OnceTask": {"text": "trip", "date": "20240402"}})
+        );
+    }
+
+    #[test]
+    fn test_read_any_version() {
+        let tasks = vec![Task::build(
+            TaskType::ProgressTask {
+                text: "book".to_string(),
+                progress: "page 1".to_string(),
+            },
+            &Clock::system(),
+        )];
+        assert_eq!(from_json(&to_json(&tasks).unwrap()).unwrap(), tasks);
+        assert_eq!(
+            from_json(&serde_json::to_vec(&tasks).unwrap()).unwrap(),
+            tasks
+        );
+        assert!(from_json(b"[]").unwrap().is_empty());
+
+        let newer = json!({"version": VERSION + 1, "tasks": []});
+        assert!(from_value(newer).is_err());
+        assert!(from_value(json!({"tasks": []})).is_err());
+        assert!(from_value(json!("tasks")).is_err());
+
+        let broken = json!({"version": VERSION, "tasks": [serde_json::to_value(&tasks[0]).unwrap(), {"id": 2}]});
+        let e = from_value(broken).unwrap_err().to_string();
+        assert!(e.contains("error: Entry 2 of the journal is not a valid task: missing field"));
+    }
+}
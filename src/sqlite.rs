@@ -0,0 +1,683 @@
+use crate::{
+    backup::Backups,
+    date,
+    history::History,
+    schema,
+    store::{TaskFilter, TaskStore},
+    task::{Task, TaskType},
+};
+use anyhow::anyhow;
+use chrono::DateTime;
+use chrono_tz::Tz;
+use colored::Colorize;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Statement};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS tasks (
+        id INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        date TEXT,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS history (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        data TEXT NOT NULL
+    );
+";
+
+/// Created once the columns are up to date, see `write_current`.
+const INDEXES: &str = "
+    CREATE INDEX IF NOT EXISTS tasks_kind_date ON tasks (kind, date);
+";
+
+/// Keeps the task list in a SQLite database.
+///
+/// Each task is stored as JSON next to a few indexed columns, so the filters of
+/// `ttd l` and `ttd t` are answered without deserializing the whole journal.
+///
+/// The database is opened read-only, and a missing one reads as an empty
+/// journal: it is only created, and its tables and tasks brought up to date,
+/// once something is written to it.
+pub struct SqliteStore {
+    path: PathBuf,
+    conn: Connection,
+    writable: bool,
+    backups: Option<Backups>,
+    /// The version of the tasks in the database, behind [`schema::VERSION`]
+    /// until they are written, or while they can't be upgraded.
+    version: u32,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<SqliteStore> {
+        let path = path.into();
+        let mut store = match path.exists() {
+            true => {
+                let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+                conn.busy_timeout(Duration::from_secs(5))?;
+                let version = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+                SqliteStore {
+                    path,
+                    conn,
+                    writable: false,
+                    backups: None,
+                    version,
+                }
+            }
+            false => {
+                let conn = Connection::open_in_memory()?;
+                conn.execute_batch(SCHEMA)?;
+                SqliteStore {
+                    path,
+                    conn,
+                    writable: false,
+                    backups: None,
+                    version: schema::VERSION,
+                }
+            }
+        };
+        // Databases from before the history was kept lack its table.
+        let tables: u32 = store.conn.query_row(
+            "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name IN ('tasks', 'history')",
+            [],
+            |row| row.get(0),
+        )?;
+        if tables < 2 {
+            store.make_writable()?;
+        }
+
+        Ok(store)
+    }
+
+    /// Reopens the database for writing, creating it and its tables if
+    /// needed and upgrading its tasks.
+    fn make_writable(&mut self) -> anyhow::Result<()> {
+        if self.writable {
+            return Ok(());
+        }
+        let conn = Connection::open(&self.path)?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch(SCHEMA)?;
+        self.version = upgrade(&conn)?;
+        self.conn = conn;
+        self.writable = true;
+
+        Ok(())
+    }
+
+    /// Runs `f` in a transaction that holds the write lock of the database.
+    fn transaction(
+        &mut self,
+        f: impl FnOnce(&mut SqliteStore) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        self.make_writable()?;
+        self.conn.execute_batch("BEGIN IMMEDIATE")?;
+        match f(self) {
+            Ok(()) => Ok(self.conn.execute_batch("COMMIT")?),
+            Err(e) => {
+                self.conn.execute_batch("ROLLBACK")?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Backs up `old_tasks`, the tasks in the database, and replaces them with
+    /// `tasks`, writing only the rows that changed. Without them, because the
+    /// rows can't be read as tasks, the raw rows are backed up and every row
+    /// is rewritten in the current format.
+    fn write_changes(&mut self, old_tasks: Option<&[Task]>, tasks: &[Task]) -> anyhow::Result<()> {
+        match (old_tasks, &self.backups) {
+            (Some(old_tasks), Some(backups)) if !old_tasks.is_empty() => {
+                backups.create(&schema::to_json(old_tasks)?)?
+            }
+            (None, Some(backups)) => {
+                let journal = read_journal(&self.conn, self.version)?;
+                backups.create(&serde_json::to_vec_pretty(&journal)?)?
+            }
+            _ => {}
+        }
+        let sp = self.conn.savepoint()?;
+        match old_tasks {
+            Some(old_tasks) => write_diff(&sp, old_tasks, tasks)?,
+            None => write_current(&sp, tasks)?,
+        }
+        sp.commit()?;
+        self.version = schema::VERSION;
+
+        Ok(())
+    }
+
+    /// Keeps the `keep` most recent versions of the task list as JSON backups.
+    pub fn with_backups(mut self, keep: usize) -> SqliteStore {
+        self.backups = Some(Backups::for_journal(&self.path, keep));
+        self
+    }
+}
+
+impl TaskStore for SqliteStore {
+    fn load(&self) -> anyhow::Result<Vec<Task>> {
+        if self.version != schema::VERSION {
+            return schema::from_value(read_journal(&self.conn, self.version)?);
+        }
+        read_tasks(&self.conn, "")
+    }
+
+    fn load_raw(&self) -> anyhow::Result<Vec<Value>> {
+        schema::upgrade(read_journal(&self.conn, self.version)?)
+    }
+
+    fn save(&mut self, tasks: &[Task]) -> anyhow::Result<()> {
+        self.make_writable()?;
+        let old_tasks = match self.version == schema::VERSION {
+            true => read_tasks(&self.conn, "").ok(),
+            false => None,
+        };
+        if old_tasks.as_deref() == Some(tasks) {
+            return Ok(());
+        }
+        self.write_changes(old_tasks.as_deref(), tasks)
+    }
+
+    /// Like the default, but only the rows of the tasks `f` changed are
+    /// written, compared with the tasks it was given.
+    fn mutate(
+        &mut self,
+        description: &str,
+        f: &mut dyn FnMut(&mut Vec<Task>) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        self.transaction(|store| {
+            let mut tasks = store.load()?;
+            let before = tasks.clone();
+            f(&mut tasks)?;
+            if tasks == before {
+                return Ok(());
+            }
+            let current = store.version == schema::VERSION;
+            store.write_changes(current.then_some(&before[..]), &tasks)?;
+            let mut history = store.load_history()?;
+            history.record(description, &before, &tasks);
+            store.save_history(&history)
+        })
+    }
+
+    fn load_history(&self) -> anyhow::Result<History> {
+        let data: Option<String> = self
+            .conn
+            .query_row("SELECT data FROM history WHERE id = 0", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        match data {
+            Some(data) => Ok(serde_json::from_str(&data)?),
+            None => Ok(History::default()),
+        }
+    }
+
+    fn save_history(&mut self, history: &History) -> anyhow::Result<()> {
+        self.make_writable()?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO history (id, data) VALUES (0, ?1)",
+            params![serde_json::to_string(history)?],
+        )?;
+
+        Ok(())
+    }
+
+    fn exclusive(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn TaskStore) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        self.transaction(|store| f(store))
+    }
+
+    /// Narrows the tasks down with the indexed columns, then lets `filter`
+    /// decide on what depends on the task itself (e.g. whether it is done).
+    fn query(&self, filter: TaskFilter, now: DateTime<Tz>) -> anyhow::Result<Vec<Task>> {
+        if self.version != schema::VERSION {
+            let tasks = self.load()?;
+            return Ok(tasks
+                .into_iter()
+                .filter(|t| filter.matches(t, now))
+                .collect());
+        }
+        // Dates are stored as `%Y%m%d`, so they compare like the days they stand for.
+        // They are on the wall clock of the task's zone, which can be a day
+        // ahead of or behind the one of `now`.
+        let today = now.date_naive();
+        let day = |offset| (today + chrono::Duration::days(offset)).format(date::DATE_FORMAT);
+        let condition = match filter {
+            TaskFilter::All => String::new(),
+            // Tasks due today at a time that has passed are expired too.
+            TaskFilter::Expired => format!("WHERE kind = 'OnceTask' AND date <= '{}'", day(1)),
+            TaskFilter::Once => "WHERE kind = 'OnceTask'".to_string(),
+            TaskFilter::Month => "WHERE kind = 'MonthTask'".to_string(),
+            TaskFilter::Week => "WHERE kind = 'WeekTask'".to_string(),
+            TaskFilter::Progress => "WHERE kind = 'ProgressTask'".to_string(),
+            TaskFilter::Recurring => "WHERE kind = 'RecurringTask'".to_string(),
+            TaskFilter::Today => format!(
+                "WHERE kind IN ('WeekTask', 'MonthTask', 'RecurringTask') OR date BETWEEN '{}' AND '{}'",
+                day(-1),
+                day(1)
+            ),
+            TaskFilter::Done => String::new(),
+        };
+        Ok(read_tasks(&self.conn, &condition)?
+            .into_iter()
+            .filter(|task| filter.matches(task, now))
+            .collect())
+    }
+
+    fn location(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn backups(&self) -> Option<&Backups> {
+        self.backups.as_ref()
+    }
+}
+
+/// Runs the tasks through the migrations of [`schema`] when the database was
+/// written by an older ttd, and returns the version the database is at. The
+/// version is kept in SQLite's `user_version`.
+///
+/// Tasks that don't deserialize after the migrations are left as they are,
+/// so `ttd doctor` can still read them through `load_raw` and write the
+/// repaired ones back in the current format.
+fn upgrade(conn: &Connection) -> anyhow::Result<u32> {
+    let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version == schema::VERSION {
+        conn.execute_batch(INDEXES)?;
+        return Ok(version);
+    }
+    let Ok(tasks) = schema::from_value(read_journal(conn, version)?) else {
+        return Ok(version);
+    };
+    conn.execute_batch("BEGIN IMMEDIATE")?;
+    match write_current(conn, &tasks) {
+        Ok(()) => {
+            conn.execute_batch("COMMIT")?;
+            Ok(schema::VERSION)
+        }
+        Err(e) => {
+            conn.execute_batch("ROLLBACK")?;
+            Err(e)
+        }
+    }
+}
+
+/// The rows of a database at `version` as a journal [`schema`] can upgrade.
+fn read_journal(conn: &Connection, version: u32) -> anyhow::Result<Value> {
+    let tasks = read_values(conn)?;
+    Ok(match version {
+        0 => Value::Array(tasks),
+        version => json!({ "version": version, "tasks": tasks }),
+    })
+}
+
+/// Replaces the tasks of a database of any version, bringing its columns and
+/// version up to date.
+fn write_current(conn: &Connection, tasks: &[Task]) -> anyhow::Result<()> {
+    upgrade_columns(conn)?;
+    write_tasks(conn, tasks)?;
+    conn.execute_batch(&format!("PRAGMA user_version = {}", schema::VERSION))?;
+    conn.execute_batch(INDEXES)?;
+
+    Ok(())
+}
+
+/// Databases from before version 2 kept the derived `status` and `ongoing`
+/// columns instead of the date of once-time tasks.
+fn upgrade_columns(conn: &Connection) -> anyhow::Result<()> {
+    let columns = conn
+        .prepare("SELECT name FROM pragma_table_info('tasks')")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    conn.execute_batch(
+        "DROP INDEX IF EXISTS tasks_kind_status;
+        DROP INDEX IF EXISTS tasks_ongoing;",
+    )?;
+    for legacy in ["status", "ongoing"] {
+        if columns.iter().any(|column| column == legacy) {
+            conn.execute_batch(&format!("ALTER TABLE tasks DROP COLUMN {}", legacy))?;
+        }
+    }
+    if !columns.iter().any(|column| column == "date") {
+        conn.execute_batch("ALTER TABLE tasks ADD COLUMN date TEXT")?;
+    }
+
+    Ok(())
+}
+
+/// The rows as JSON. A row that isn't JSON at all is kept as the text it
+/// holds, so the doctor can report and quarantine it like any broken task.
+fn read_values(conn: &Connection) -> anyhow::Result<Vec<Value>> {
+    let mut stmt = conn.prepare("SELECT data FROM tasks ORDER BY id")?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    let mut tasks = Vec::new();
+    for data in rows {
+        let data = data?;
+        match serde_json::from_str(&data) {
+            Ok(task) => tasks.push(task),
+            Err(_) => tasks.push(Value::String(data)),
+        }
+    }
+
+    Ok(tasks)
+}
+
+fn read_tasks(conn: &Connection, condition: &str) -> anyhow::Result<Vec<Task>> {
+    let sql = format!("SELECT id, data FROM tasks {} ORDER BY id", condition);
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut tasks = Vec::new();
+    for row in rows {
+        let (id, data) = row?;
+        let task = serde_json::from_str(&data)
+            .map_err(|e| schema::invalid_entry(usize::try_from(id).unwrap_or_default(), e))?;
+        tasks.push(task);
+    }
+
+    Ok(tasks)
+}
+
+fn write_tasks(conn: &Connection, tasks: &[Task]) -> anyhow::Result<()> {
+    conn.execute("DELETE FROM tasks", [])?;
+    let mut stmt = conn.prepare(UPSERT)?;
+    tasks.iter().try_for_each(|task| upsert(&mut stmt, task))
+}
+
+/// Turns the rows of `old_tasks` into those of `tasks`, leaving the rows of
+/// the tasks that didn't change alone.
+fn write_diff(conn: &Connection, old_tasks: &[Task], tasks: &[Task]) -> anyhow::Result<()> {
+    let old_by_id: HashMap<usize, &Task> = old_tasks.iter().map(|task| (task.id, task)).collect();
+    let mut stmt = conn.prepare(UPSERT)?;
+    tasks
+        .iter()
+        .filter(|task| old_by_id.get(&task.id) != Some(task))
+        .try_for_each(|task| upsert(&mut stmt, task))?;
+    let ids: HashSet<usize> = tasks.iter().map(|task| task.id).collect();
+    let mut stmt = conn.prepare("DELETE FROM tasks WHERE id = ?1")?;
+    for id in old_by_id.keys().filter(|id| !ids.contains(id)) {
+        stmt.execute(params![row_id(*id)?])?;
+    }
+
+    Ok(())
+}
+
+const UPSERT: &str = "INSERT INTO tasks (id, kind, date, data) VALUES (?1, ?2, ?3, ?4)
+    ON CONFLICT (id) DO UPDATE SET kind = excluded.kind, date = excluded.date, data = excluded.data";
+
+fn upsert(stmt: &mut Statement, task: &Task) -> anyhow::Result<()> {
+    let (kind, date) = match &task.content {
+        TaskType::WeekTask { .. } => ("WeekTask", None),
+        TaskType::MonthTask { .. } => ("MonthTask", None),
+        TaskType::OnceTask { date, .. } => {
+            ("OnceTask", Some(date.format(date::DATE_FORMAT).to_string()))
+        }
+        TaskType::ProgressTask { .. } => ("ProgressTask", None),
+        TaskType::RecurringTask { .. } => ("RecurringTask", None),
+    };
+    stmt.execute(params![
+        row_id(task.id)?,
+        kind,
+        date,
+        serde_json::to_string(task)?
+    ])?;
+
+    Ok(())
+}
+
+fn row_id(id: usize) -> anyhow::Result<i64> {
+    i64::try_from(id).map_err(|_| anyhow!("error: Invalid task index!".bright_red()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::Clock;
+
+    #[test]
+    fn test_sqlite_store_query() {
+        let mut store = SqliteStore::open(":memory:").unwrap();
+        let mut gym = Task::build(
+            TaskType::WeekTask {
+                text: "gym".to_string(),
+                weekdays: vec![chrono::Weekday::Tue],
+                time: None,
+            },
+            &Clock::system(),
+        );
+        gym.id = 1;
+        let mut trip = Task::build(
+            TaskType::OnceTask {
+                text: "trip".to_string(),
+                date: chrono::NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(),
+                time: chrono::NaiveTime::from_hms_opt(18, 0, 0),
+                zone: None,
+                remind: None,
+            },
+            &Clock::system(),
+        );
+        trip.id = 2;
+        store.save(&[gym, trip]).unwrap();
+
+        let today = Clock::fixed(
+            chrono::NaiveDate::from_ymd_opt(2024, 4, 2)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap(),
+        )
+        .now();
+        let yesterday = today - chrono::Duration::days(1);
+        assert_eq!(store.load().unwrap().len(), 2);
+        assert_eq!(store.query(TaskFilter::Today, today).unwrap()[0].id, 1);
+        assert_eq!(store.query(TaskFilter::Today, yesterday).unwrap()[0].id, 2);
+        assert_eq!(store.query(TaskFilter::Expired, today).unwrap()[0].id, 2);
+        assert!(store
+            .query(TaskFilter::Expired, yesterday)
+            .unwrap()
+            .is_empty());
+        let yesterday_evening = yesterday + chrono::Duration::hours(7);
+        assert_eq!(
+            store.query(TaskFilter::Expired, yesterday_evening).unwrap()[0].id,
+            2
+        );
+        assert!(store.query(TaskFilter::Progress, today).unwrap().is_empty());
+
+        store
+            .mutate("remove 1", &mut |tasks| {
+                tasks.remove(0);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(store.load().unwrap().len(), 1);
+        assert_eq!(store.load_history().unwrap().operations.len(), 1);
+    }
+
+    #[test]
+    fn test_upgrade_old_database() {
+        let path =
+            std::env::temp_dir().join(format!("ttd-test-upgrade-{}.sqlite", std::process::id()));
+        let conn = Connection::open(&path).unwrap();
+        // The layout written by the first versions of the SQLite backend.
+        conn.execute_batch(
+            "CREATE TABLE tasks (
+                id INTEGER PRIMARY KEY,
+                kind TEXT NOT NULL,
+                status TEXT,
+                ongoing INTEGER NOT NULL DEFAULT 0,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX tasks_kind_status ON tasks (kind, status);
+            CREATE INDEX tasks_ongoing ON tasks (ongoing);",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO tasks (id, kind, data) VALUES (1, 'ProgressTask', ?1)",
+            [r#"{"id":1,"created_at":"2024-04-01 08:00:00","content":{"ProgressTask":{"text":"book","progress":"page 1"}}}"#],
+        )
+        .unwrap();
+        drop(conn);
+
+        let store = SqliteStore::open(&path).unwrap();
+        let tasks = store.load().unwrap();
+        assert_eq!(tasks.len(), 1);
+        let version: u32 = store
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, schema::VERSION);
+        drop(store);
+        assert_eq!(SqliteStore::open(&path).unwrap().load().unwrap(), tasks);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_database_with_broken_task() {
+        let path =
+            std::env::temp_dir().join(format!("ttd-test-broken-{}.sqlite", std::process::id()));
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE tasks (id INTEGER PRIMARY KEY, kind TEXT NOT NULL, data TEXT NOT NULL);
+            PRAGMA user_version = 1;",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO tasks (id, kind, data) VALUES (1, 'ProgressTask', ?1), (2, 'OnceTask', ?2)",
+            [
+                r#"{"id":1,"uid":"6f9e2c1a-3b4d-4e5f-8a7b-1c2d3e4f5a6b","created_at":"2024-04-01 08:00:00","content":{"ProgressTask":{"text":"book","progress":"page 1"}}}"#,
+                r#"{"id":2,"uid":"0d1e2f3a-4b5c-4d6e-8f7a-8b9c0d1e2f3a","created_at":"2024-04-01 08:00:00","content":{"OnceTask":{"text":"trip","date":"2024-13-01"}}}"#,
+            ],
+        )
+        .unwrap();
+        drop(conn);
+
+        let mut store = SqliteStore::open(&path).unwrap();
+        assert!(store.load().is_err());
+        assert_eq!(store.load_raw().unwrap().len(), 2);
+        let book: Task = serde_json::from_value(store.load_raw().unwrap().remove(0)).unwrap();
+        let book = vec![book];
+        store.save(&book).unwrap();
+        drop(store);
+        assert_eq!(SqliteStore::open(&path).unwrap().load().unwrap(), book);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_doctor_quarantines_corrupt_row() {
+        let path =
+            std::env::temp_dir().join(format!("ttd-test-corrupt-{}.sqlite", std::process::id()));
+        let mut store = SqliteStore::open(&path).unwrap();
+        let book = Task::build(
+            TaskType::ProgressTask {
+                text: "book".to_string(),
+                progress: "page 1".to_string(),
+            },
+            &Clock::system(),
+        );
+        store.save(&[Task { id: 1, ..book }]).unwrap();
+        store
+            .conn
+            .execute(
+                "INSERT INTO tasks (id, kind, data) VALUES (2, 'OnceTask', '{not json')",
+                [],
+            )
+            .unwrap();
+        assert!(store.load().is_err());
+        assert!(matches!(&store.load_raw().unwrap()[1], Value::String(raw) if raw == "{not json"));
+
+        crate::storage::doctor(&mut store, true).unwrap();
+        assert_eq!(store.load().unwrap().len(), 1);
+        let quarantine_path = path.with_extension("quarantine.json");
+        let quarantined: Vec<Value> =
+            serde_json::from_slice(&std::fs::read(&quarantine_path).unwrap()).unwrap();
+        assert_eq!(quarantined, vec![Value::String("{not json".to_string())]);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&quarantine_path).unwrap();
+    }
+
+    #[test]
+    fn test_mutate_writes_only_changed_rows() {
+        let mut store = SqliteStore::open(":memory:").unwrap();
+        let tasks: Vec<Task> = (1..=3)
+            .map(|id| Task {
+                id,
+                ..Task::build(
+                    TaskType::ProgressTask {
+                        text: format!("book {}", id),
+                        progress: "page 1".to_string(),
+                    },
+                    &Clock::system(),
+                )
+            })
+            .collect();
+        store.save(&tasks).unwrap();
+        // A row that is rewritten loses this mark.
+        store
+            .conn
+            .execute("UPDATE tasks SET kind = 'untouched'", [])
+            .unwrap();
+
+        store
+            .mutate("edit", &mut |tasks| {
+                tasks.retain(|task| task.id != 3);
+                if let TaskType::ProgressTask { progress, .. } = &mut tasks[1].content {
+                    *progress = "page 2".to_string();
+                }
+                Ok(())
+            })
+            .unwrap();
+        let kinds: Vec<(i64, String)> = store
+            .conn
+            .prepare("SELECT id, kind FROM tasks ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            kinds,
+            vec![
+                (1, "untouched".to_string()),
+                (2, "ProgressTask".to_string())
+            ]
+        );
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0], tasks[0]);
+        assert!(
+            matches!(&loaded[1].content, TaskType::ProgressTask { progress, .. } if progress == "page 2")
+        );
+        assert_eq!(store.load_history().unwrap().operations.len(), 1);
+    }
+
+    #[test]
+    fn test_open_missing_database() {
+        let path =
+            std::env::temp_dir().join(format!("ttd-test-missing-{}.sqlite", std::process::id()));
+        let mut store = SqliteStore::open(&path).unwrap();
+        assert!(store.load().unwrap().is_empty());
+        assert!(store.load_history().unwrap().operations.is_empty());
+        assert!(!path.exists());
+
+        let book = Task::build(
+            TaskType::ProgressTask {
+                text: "book".to_string(),
+                progress: "page 1".to_string(),
+            },
+            &Clock::system(),
+        );
+        store.save(&[Task { id: 1, ..book }]).unwrap();
+        drop(store);
+        assert_eq!(SqliteStore::open(&path).unwrap().load().unwrap().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
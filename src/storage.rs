@@ -1,161 +1,495 @@
 use crate::{
-    date,
+    backup,
+    config::{Colors, Config},
+    date::{self, Clock},
+    doctor,
+    history::History,
+    rrule::Rule,
+    store::{TaskFilter, TaskStore},
     task::{OnceDateStatus, Task, TaskType},
 };
 use anyhow::anyhow;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime};
+use chrono_tz::Tz;
 use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{
-    fs::{File, OpenOptions},
-    io::{self, BufRead, Seek, SeekFrom, Write},
-    path::PathBuf,
+    env, fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::Command,
 };
+use uuid::Uuid;
 
-fn get_path() -> anyhow::Result<PathBuf> {
-    home::home_dir()
-        .map(|mut path| {
-            path.push(".ttd.json");
-            path
-        })
-        .ok_or(anyhow!("Could not find home directory.".bright_red()))
-}
-
-pub fn init() -> anyhow::Result<()> {
-    let path = get_path()?;
-    let file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(path)?;
-    let mut tasks = collect_tasks(&file)?;
-    if !tasks.is_empty() {
-        tasks.iter_mut().for_each(|t| match &mut t.content {
-            TaskType::OnceTask {
-                text: _,
-                date,
-                ref mut status,
-            } => {
-                *status = date::date_check(&date);
-            }
-            TaskType::WeekTask {
-                text: _,
-                weekday,
-                ref mut ongoing,
-            } => {
-                *ongoing = date::weekday_check(weekday);
-            }
-            TaskType::MonthTask {
-                text: _,
-                day,
-                ref mut ongoing,
-            } => {
-                *ongoing = date::day_check(*day);
-            }
-            _ => {}
-        })
+/// Makes sure the journal can be read and holds no task the other commands
+/// would trip over. Nothing is written.
+pub fn check_journal(store: &dyn TaskStore) -> anyhow::Result<()> {
+    let tasks = store
+        .load()
+        .map_err(|e| anyhow!("{}\n{}", e, DOCTOR_HINT.bright_yellow()))?;
+    if let Some(task) = tasks.iter().find(|t| doctor::check_task(t).is_some()) {
+        return Err(anyhow!(
+            "{}{}\n{}",
+            "error: The journal has an invalid task: #".bright_red(),
+            task.id.to_string().bright_red(),
+            DOCTOR_HINT.bright_yellow()
+        ));
     }
-    file.set_len(0)?;
-    serde_json::to_writer_pretty(file, &tasks)?;
 
     Ok(())
 }
 
-pub fn path_check() -> anyhow::Result<PathBuf> {
-    let path = get_path()?;
-    if path.exists() {
-        Ok(path)
-    } else {
-        Err(anyhow!(
+const DOCTOR_HINT: &str = "Run `ttd doctor` to find and fix the problems in the journal.";
+
+pub fn path_check(store: &dyn TaskStore) -> anyhow::Result<PathBuf> {
+    match store.location() {
+        Some(path) if path.exists() => Ok(path.to_path_buf()),
+        _ => Err(anyhow!(
             "The specified file cannot be found. Please restart the program.".bright_red()
-        ))
+        )),
     }
 }
 
-fn collect_tasks(mut file: &File) -> anyhow::Result<Vec<Task>> {
-    file.seek(SeekFrom::Start(0))?;
-    let tasks: Vec<Task> = match serde_json::from_reader(file) {
-        Ok(tasks) => tasks,
-        Err(e) if e.is_eof() => Vec::new(),
-        Err(e) => Err(e)?,
-    };
-    file.seek(SeekFrom::Start(0))?;
-
-    Ok(tasks)
-}
-
-pub fn update_bookmark(id: usize, new_progress: String) -> anyhow::Result<()> {
-    let path = get_path()?;
-    let file = OpenOptions::new().read(true).write(true).open(path)?;
-    let mut tasks = collect_tasks(&file)?;
-    if let Some(task) = tasks.get_mut(id - 1) {
+pub fn update_bookmark(
+    store: &mut dyn TaskStore,
+    id: &str,
+    new_progress: String,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let mut updated = None;
+    let description = format!("update {} {}", id, new_progress);
+    store.mutate(&description, &mut |tasks| {
+        let index = resolve_id(tasks, id)?;
+        let task = &mut tasks[index];
         if let TaskType::ProgressTask {
             text: _,
             progress: ref mut p,
         } = &mut task.content
         {
-            *p = new_progress;
+            *p = new_progress.clone();
         } else {
             return Err(anyhow!("error: The task is not a bookmark, please enter a valid index (e.g. 1, 2, 3, etc.)".bright_red()));
         }
-    } else {
-        return Err(anyhow!(
-            "error: Invalid index, please enter a valid index (e.g. 1, 2, 3, etc.)".bright_red()
-        ));
+        updated = Some(task.clone());
+        Ok(())
+    })?;
+    if let Some(new_book_mark) = updated {
+        println!(
+            "{} {}",
+            "page updated!:".bright_green(),
+            new_book_mark.format_info(clock.now())
+        );
     }
-    file.set_len(0)?;
-    serde_json::to_writer_pretty(file, &tasks)?;
-    let new_book_mark = tasks.get(id - 1).unwrap();
-    println!("{} {}", "page updated!:".bright_green(), new_book_mark);
 
     Ok(())
 }
 
+pub fn complete_task(store: &mut dyn TaskStore, id: &str, clock: &Clock) -> anyhow::Result<()> {
+    let mut completed = None;
+    store.mutate(&format!("done {}", id), &mut |tasks| {
+        let index = resolve_id(tasks, id)?;
+        let task = &mut tasks[index];
+        if task.is_done(clock.now()) {
+            return Err(anyhow!(
+                "{}",
+                "error: The task is already done!".bright_red()
+            ));
+        }
+        task.completed_at = Some(clock.timestamp());
+        completed = Some(task.clone());
+        Ok(())
+    })?;
+    if let Some(task) = completed {
+        println!(
+            "{} {}",
+            "Task done!:".bright_green(),
+            task.format_info(clock.now())
+        );
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn parse_task(
     text: String,
     weekday: Option<String>,
-    day: Option<usize>,
+    day: Option<String>,
     date: Option<String>,
     progress: Option<String>,
+    rule: Option<String>,
+    remind: Option<String>,
+    config: &Config,
+    clock: &Clock,
 ) -> anyhow::Result<Task> {
-    match (weekday, day, date, progress) {
-        (Some(w), _, _, _) => {
-            let w = date::parse_weekday(&w)?.to_string();
-            let ongoing = date::weekday_check(&w);
-            Ok(Task::build(TaskType::WeekTask { text, weekday: w, ongoing }))
+    if remind.is_some() && date.is_none() {
+        return Err(anyhow!(
+            "{}",
+            "error: Only once-time tasks can have a reminder!".bright_red()
+        ));
+    }
+    match (weekday, day, date, progress, rule) {
+        (Some(w), _, _, _, _) => {
+            let (w, time) = date::split_time(&w)?;
+            let weekdays = date::parse_weekdays(w)?;
+            Ok(Task::build(TaskType::WeekTask { text, weekdays, time }, clock))
         },
-        (_, Some(d), _, _) => {
-            if d <= 0 || d > 31 {
-                return Err(anyhow!("error: Invalid day, please enter a valid day (e.g. 1, 2, 3, etc.)".bright_red()));
-            }
-            let ongoing = date::day_check(d);
-            Ok(Task::build(TaskType::MonthTask { text, day: d, ongoing }))
+        (_, Some(d), _, _, _) => {
+            let (d, time) = date::split_time(&d)?;
+            let day = date::parse_monthday(d)?;
+            let overflow = config.month_overflow;
+            Ok(Task::build(TaskType::MonthTask { text, day, overflow, time }, clock))
+        },
+        (_, _, Some(d), _, _) => {
+            let (d, time) = date::split_time(&d)?;
+            let d = date::parse_user_date(d, &config.date_format, clock.today())?;
+            let remind = remind
+                .or_else(|| config.remind.clone())
+                .map(|remind| date::parse_lead_time(&remind))
+                .transpose()?;
+            // A time is kept on the wall clock of the zone it was given in.
+            let zone = time.map(|_| clock.zone());
+            Ok(Task::build(TaskType::OnceTask { text, date: d, time, zone, remind }, clock))
         },
-        (_, _, Some(d), _) => {
-            let d = date::parse_date(&d)?.format("%Y%m%d").to_string();
-            let status = date::date_check(&d);
-            if let OnceDateStatus::Expired = status {
-                println!("{}", "warning: The task has expired.".bright_yellow());
+        (_, _, _, Some(p), _) => Ok(Task::build(TaskType::ProgressTask { text, progress: p }, clock)),
+        (_, _, _, _, Some(r)) => {
+            let rule = Rule::parse(&r, clock.today())?;
+            if rule.occurrences().next().is_none() {
+                println!("{}", "warning: The rule never occurs.".bright_yellow());
             }
-            Ok(Task::build(TaskType::OnceTask { text, date: d, status }))
+            Ok(Task::build(TaskType::RecurringTask { text, rule }, clock))
         },
-        (_, _, _, Some(p)) => Ok(Task::build(TaskType::ProgressTask { text, progress: p })),
-        _ => return Err(anyhow!("error: Invalid task type, please enter a valid task type (e.g. WeekTask, MonthTask, OnceTask, BookMark)".bright_red()))
+        _ => Err(anyhow!("error: Invalid task type, please enter a valid task type (e.g. WeekTask, MonthTask, OnceTask, BookMark)".bright_red()))
     }
 }
 
-pub fn add_task(mut task: Task) -> anyhow::Result<()> {
-    let path = get_path()?;
-    let file = OpenOptions::new().read(true).write(true).open(path)?;
-    let mut tasks = collect_tasks(&file)?;
-    task.id = tasks.len() + 1;
-    let msg = format!("{}", task);
-    tasks.push(task);
-    serde_json::to_writer_pretty(file, &tasks)?;
+pub fn add_task(store: &mut dyn TaskStore, task: Task, clock: &Clock) -> anyhow::Result<()> {
+    warn_if_expired(&task, clock);
+    let description = format!("add {}", task.content.text());
+    let mut task = Some(task);
+    let mut msg = String::new();
+    store.mutate(&description, &mut |tasks| {
+        if let Some(mut task) = task.take() {
+            task.id = tasks.len() + 1;
+            msg = task.format_info(clock.now());
+            tasks.push(task);
+        }
+        Ok(())
+    })?;
     println!("{} {}", "Task added:".bright_green(), msg);
 
     Ok(())
 }
 
-pub fn handle_user_input() -> anyhow::Result<()> {
+/// The fields of a task that `ttd edit` can change, mirroring the arguments of `ttd a`.
+/// It is also the document opened in the editor by `ttd edit --editor`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TaskEdit {
+    pub text: Option<String>,
+    pub weekday: Option<String>,
+    pub day: Option<String>,
+    pub date: Option<String>,
+    pub progress: Option<String>,
+    pub rule: Option<String>,
+    /// The lead time of the reminder of a once-time task, e.g. `3d`.
+    pub remind: Option<String>,
+}
+
+impl TaskEdit {
+    /// The date and time of a once-time task are shown on the wall clock of `zone`.
+    fn from_task(task: &Task, config: &Config, zone: Tz) -> TaskEdit {
+        match &task.content {
+            TaskType::WeekTask {
+                text,
+                weekdays,
+                time,
+            } => TaskEdit {
+                text: Some(text.clone()),
+                weekday: Some(with_input_time(date::format_weekdays(weekdays), time)),
+                ..Default::default()
+            },
+            TaskType::MonthTask {
+                text, day, time, ..
+            } => TaskEdit {
+                text: Some(text.clone()),
+                day: Some(with_input_time(date::format_monthday(*day), time)),
+                ..Default::default()
+            },
+            TaskType::OnceTask {
+                text,
+                date,
+                time,
+                zone: home,
+                remind,
+            } => TaskEdit {
+                text: Some(text.clone()),
+                // Shown in the user's format so it parses back through `parse_task`.
+                date: Some({
+                    let (date, time) = date::move_to_zone(*date, *time, *home, zone);
+                    with_input_time(date.format(&config.date_format).to_string(), &time)
+                }),
+                remind: remind.map(|days| format!("{}d", days)),
+                ..Default::default()
+            },
+            TaskType::ProgressTask { text, progress } => TaskEdit {
+                text: Some(text.clone()),
+                progress: Some(progress.clone()),
+                ..Default::default()
+            },
+            TaskType::RecurringTask { text, rule } => TaskEdit {
+                text: Some(text.clone()),
+                rule: Some(rule.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn type_count(&self) -> usize {
+        [
+            self.weekday.is_some(),
+            self.day.is_some(),
+            self.date.is_some(),
+            self.progress.is_some(),
+            self.rule.is_some(),
+        ]
+        .into_iter()
+        .filter(|set| *set)
+        .count()
+    }
+
+    /// Applies `self` on top of `task`: the text is kept unless a new one is
+    /// given, and the type is kept unless a new one is given.
+    fn apply(self, task: &Task, config: &Config, clock: &Clock) -> anyhow::Result<Task> {
+        if self.type_count() > 1 {
+            return Err(anyhow!(
+                "{}",
+                "error: Invalid task type, please set only one of weekday, day, date, progress and rule"
+                    .bright_red()
+            ));
+        }
+        let mut current = TaskEdit::from_task(task, config, clock.zone());
+        let text = self
+            .text
+            .clone()
+            .or(current.text.take())
+            .unwrap_or_default();
+        let remind = self.remind.clone();
+        let new_date = self.date.is_some();
+        let TaskEdit {
+            weekday,
+            day,
+            date,
+            progress,
+            rule,
+            ..
+        } = if self.type_count() == 0 {
+            current
+        } else {
+            self
+        };
+        let new_remind = remind.is_some();
+        let mut edited = parse_task(
+            text, weekday, day, date, progress, rule, remind, config, clock,
+        )?;
+        // The overflow policy is chosen when the task is added, not when it is edited.
+        if let (
+            TaskType::MonthTask { overflow, .. },
+            TaskType::MonthTask {
+                overflow: previous, ..
+            },
+        ) = (&mut edited.content, &task.content)
+        {
+            *overflow = *previous;
+        }
+        // So is the reminder, unless a new one is given.
+        if let (
+            TaskType::OnceTask { remind, .. },
+            TaskType::OnceTask {
+                remind: previous, ..
+            },
+        ) = (&mut edited.content, &task.content)
+        {
+            if !new_remind {
+                *remind = *previous;
+            }
+        }
+        // A new date or repetition given without a time keeps the time of
+        // day of the task, and a once-time task the zone it was set in.
+        let previous = match &task.content {
+            TaskType::WeekTask { time, .. } | TaskType::MonthTask { time, .. } => {
+                time.map(|time| (time, Some(clock.zone())))
+            }
+            TaskType::OnceTask { time, zone, .. } => time.map(|time| (time, *zone)),
+            _ => None,
+        };
+        if let Some((previous_time, previous_zone)) = previous {
+            match &mut edited.content {
+                TaskType::WeekTask { time, .. } | TaskType::MonthTask { time, .. }
+                    if time.is_none() =>
+                {
+                    *time = Some(previous_time)
+                }
+                TaskType::OnceTask { time, zone, .. } if time.is_none() => {
+                    (*time, *zone) = (Some(previous_time), previous_zone)
+                }
+                _ => {}
+            }
+        }
+        // A date that is not given again stays in the zone it was set in.
+        if let (
+            TaskType::OnceTask {
+                date, time, zone, ..
+            },
+            TaskType::OnceTask {
+                date: previous_date,
+                time: previous_time,
+                zone: previous_zone,
+                ..
+            },
+        ) = (&mut edited.content, &task.content)
+        {
+            if !new_date {
+                (*date, *time, *zone) = (*previous_date, *previous_time, *previous_zone);
+            }
+        }
+        edited.id = task.id;
+        edited.uid = task.uid;
+        edited.created_at = task.created_at;
+        edited.completed_at = task.completed_at;
+
+        Ok(edited)
+    }
+}
+
+/// Appends the time of day of a task in the `@HH:MM` form `parse_task` reads.
+fn with_input_time(value: String, time: &Option<NaiveTime>) -> String {
+    match time {
+        Some(time) => format!("{}@{}", value, time.format(date::TIME_OF_DAY_FORMAT)),
+        None => value,
+    }
+}
+
+pub fn edit_task(
+    store: &mut dyn TaskStore,
+    id: &str,
+    edit: TaskEdit,
+    config: &Config,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let mut edit = Some(edit);
+    let mut edited = None;
+    store.mutate(&format!("edit {}", id), &mut |tasks| {
+        let index = resolve_id(tasks, id)?;
+        if let Some(edit) = edit.take() {
+            let task = edit.apply(&tasks[index], config, clock)?;
+            if moved(&tasks[index], &task) {
+                warn_if_expired(&task, clock);
+            }
+            edited = Some(task.clone());
+            tasks[index] = task;
+        }
+        Ok(())
+    })?;
+    if let Some(task) = edited {
+        println!(
+            "{} {}",
+            "Task edited:".bright_green(),
+            task.format_info(clock.now())
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether an edit gave a once-time task a new date or time.
+fn moved(task: &Task, edited: &Task) -> bool {
+    match (task.content.due_in(Tz::UTC), edited.content.due_in(Tz::UTC)) {
+        (Some(due), Some(new_due)) => due != new_due,
+        _ => true,
+    }
+}
+
+/// Warns about a once-time task added or moved to a date that has passed.
+fn warn_if_expired(task: &Task, clock: &Clock) {
+    if task.content.once_status(clock.now()) == Some(OnceDateStatus::Expired) {
+        println!("{}", "warning: The task has expired.".bright_yellow());
+    }
+}
+
+/// Opens the task as JSON in `$VISUAL` or `$EDITOR` and saves the result.
+pub fn edit_task_in_editor(
+    store: &mut dyn TaskStore,
+    id: &str,
+    config: &Config,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let tasks = store.load()?;
+    let task = &tasks[resolve_id(&tasks, id)?];
+    // A fresh, unpredictable name that is never an existing file or symlink.
+    let path = env::temp_dir().join(format!(
+        "ttd-edit-{}-{}.json",
+        task.short_uid(),
+        Uuid::new_v4().simple()
+    ));
+    let mut file = create_private(&path)?;
+    file.write_all(
+        serde_json::to_string_pretty(&TaskEdit::from_task(task, config, clock.zone()))?.as_bytes(),
+    )?;
+    drop(file);
+    let result = run_editor(&path).and_then(|_| {
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str::<TaskEdit>(&content).map_err(|e| {
+            anyhow!(
+                "{}{}",
+                "error: Invalid task, the edit has been discarded: ".bright_red(),
+                e.to_string().bright_red()
+            )
+        })
+    });
+    let _ = fs::remove_file(&path);
+    edit_task(
+        store,
+        &task.uid.simple().to_string(),
+        result?,
+        config,
+        clock,
+    )
+}
+
+/// Creates a new file that only the current user can read, failing if `path` exists.
+fn create_private(path: &Path) -> io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+fn run_editor(path: &Path) -> anyhow::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or(anyhow!("{}", "error: $EDITOR is empty!".bright_red()))?;
+    let status = Command::new(program).args(words).arg(path).status()?;
+    if !status.success() {
+        return Err(anyhow!(
+            "{}",
+            "error: The editor exited with an error, the edit has been discarded.".bright_red()
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn handle_user_input(
+    store: &mut dyn TaskStore,
+    config: &Config,
+    clock: &Clock,
+) -> anyhow::Result<()> {
     println!("{}", "Enable multi-line input mode".bright_green());
     println!("{}", "Please enter tasks to be added in the format: Task content + task type + Weekday/monthday/date/page. ".bright_green());
     println!(
@@ -178,12 +512,12 @@ pub fn handle_user_input() -> anyhow::Result<()> {
                     println!("{}", "Exit multi-line input mode".bright_green());
                     break;
                 }
-                let input = input.split(" ").collect::<Vec<&str>>();
+                let input = input.split(' ').collect::<Vec<&str>>();
                 if input.len() == 3 {
                     let task_content = (input[0], input[2]);
                     let task_type = input[1];
-                    if let Ok(task) = parse_input(task_content, task_type) {
-                        add_task(task)?;
+                    if let Ok(task) = parse_input(task_content, task_type, config, clock) {
+                        add_task(store, task, clock)?;
                     } else {
                         println!("{}", "error: Invalid input!".bright_red());
                         continue;
@@ -203,7 +537,12 @@ pub fn handle_user_input() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn parse_input(task_content: (&str, &str), task_type: &str) -> anyhow::Result<Task> {
+fn parse_input(
+    task_content: (&str, &str),
+    task_type: &str,
+    config: &Config,
+    clock: &Clock,
+) -> anyhow::Result<Task> {
     match task_type {
         "-w" => Ok(parse_task(
             task_content.0.to_owned(),
@@ -211,13 +550,21 @@ fn parse_input(task_content: (&str, &str), task_type: &str) -> anyhow::Result<Ta
             None,
             None,
             None,
+            None,
+            None,
+            config,
+            clock,
         )?),
         "-m" => Ok(parse_task(
             task_content.0.to_owned(),
             None,
-            Some(task_content.1.parse::<usize>()?),
+            Some(task_content.1.to_owned()),
             None,
             None,
+            None,
+            None,
+            config,
+            clock,
         )?),
         "-o" => Ok(parse_task(
             task_content.0.to_owned(),
@@ -225,6 +572,10 @@ fn parse_input(task_content: (&str, &str), task_type: &str) -> anyhow::Result<Ta
             None,
             Some(task_content.1.to_owned()),
             None,
+            None,
+            None,
+            config,
+            clock,
         )?),
         "-p" => Ok(parse_task(
             task_content.0.to_owned(),
@@ -232,230 +583,213 @@ fn parse_input(task_content: (&str, &str), task_type: &str) -> anyhow::Result<Ta
             None,
             None,
             Some(task_content.1.to_owned()),
+            None,
+            None,
+            config,
+            clock,
         )?),
-        _ => return Err(anyhow!("{}", "error: Invalid task type!".bright_red())),
+        "-r" => Ok(parse_task(
+            task_content.0.to_owned(),
+            None,
+            None,
+            None,
+            None,
+            Some(task_content.1.to_owned()),
+            None,
+            config,
+            clock,
+        )?),
+        _ => Err(anyhow!("{}", "error: Invalid task type!".bright_red())),
     }
 }
 
-pub fn remove_task_by_id(id: Option<usize>) -> anyhow::Result<()> {
-    let path = get_path()?;
-    let file = OpenOptions::new().read(true).write(true).open(path)?;
-    let mut tasks = collect_tasks(&file)?;
-    let index = match id {
-        Some(id) => {
-            if id == 0 || id > tasks.len() {
-                return Err(anyhow!(
-                    "{}{}",
-                    "error: Invalid task index! the task index should be between 1 and "
-                        .bright_red(),
-                    tasks.len().to_string().bright_red()
-                ));
-            } else {
-                id - 1
-            }
-        }
-        None => {
-            if tasks.is_empty() {
-                return Err(anyhow!(
-                    "{}",
-                    "error: There are no tasks to remove!".bright_red()
-                ));
-            } else {
-                tasks.len() - 1
-            }
-        }
+pub fn remove_task_by_id(
+    store: &mut dyn TaskStore,
+    id: Option<&str>,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let mut removed_task = None;
+    let description = match id {
+        Some(id) => format!("remove {}", id),
+        None => "remove last task".to_string(),
     };
-    let removed_task = tasks.remove(index);
-    let tasks = id_reset(tasks);
-    file.set_len(0)?;
-    serde_json::to_writer_pretty(file, &tasks)?;
-    println!("{} {}", "Task removed!:".bright_yellow(), removed_task);
+    store.mutate(&description, &mut |tasks| {
+        let index = match id {
+            Some(id) => resolve_id(tasks, id)?,
+            None => {
+                if tasks.is_empty() {
+                    return Err(anyhow!(
+                        "{}",
+                        "error: There are no tasks to remove!".bright_red()
+                    ));
+                } else {
+                    tasks.len() - 1
+                }
+            }
+        };
+        removed_task = Some(tasks.remove(index));
+        id_reset(tasks);
+        Ok(())
+    })?;
+    if let Some(removed_task) = removed_task {
+        println!(
+            "{} {}",
+            "Task removed!:".bright_yellow(),
+            removed_task.format_info(clock.now())
+        );
+    }
 
     Ok(())
 }
 
-pub fn clear_tasks() -> anyhow::Result<()> {
-    let path = get_path()?;
-    let file = OpenOptions::new().read(true).write(true).open(path)?;
-    let tasks = collect_tasks(&file)?;
-    file.set_len(0)?;
+pub fn clear_tasks(store: &mut dyn TaskStore) -> anyhow::Result<()> {
+    let mut count = 0;
+    store.mutate("clear all tasks", &mut |tasks| {
+        count = tasks.len();
+        tasks.clear();
+        Ok(())
+    })?;
     println!(
         "{}{}",
         "Task list cleared! count: ".bright_yellow(),
-        tasks.len().to_string().bright_yellow()
+        count.to_string().bright_yellow()
     );
 
     Ok(())
 }
 
 pub fn remove_tasks_by_filter(
-    expired: bool,
-    once_task: bool,
-    month_task: bool,
-    week_task: bool,
-    progress_task: bool,
+    store: &mut dyn TaskStore,
+    filter: TaskFilter,
+    clock: &Clock,
 ) -> anyhow::Result<()> {
-    let path = get_path()?;
-    let file = OpenOptions::new().read(true).write(true).open(path)?;
-    let tasks = collect_tasks(&file)?;
-    if tasks.is_empty() {
+    let now = clock.now();
+    if let TaskFilter::All = filter {
+        return Err(anyhow!("{}", "error: Invalid filter!".bright_red()));
+    }
+    let mut removed_tasks = Vec::new();
+    let mut empty = false;
+    let description = format!("remove {:?} tasks", filter).to_lowercase();
+    store.mutate(&description, &mut |tasks| {
+        if tasks.is_empty() {
+            empty = true;
+            return Ok(());
+        }
+        let (retained, removed): (Vec<Task>, Vec<Task>) =
+            tasks.drain(..).partition(|task| !filter.matches(task, now));
+        *tasks = retained;
+        id_reset(tasks);
+        removed_tasks = removed;
+        Ok(())
+    })?;
+    if empty {
         println!("{}", "warning: Task list is empty!".bright_yellow());
         return Ok(());
     }
-    let origin_len = tasks.len();
-    let (retained_tasks, removed_tasks): (Vec<Task>, Vec<Task>) =
-        match (expired, once_task, month_task, week_task, progress_task) {
-            (true, _, _, _, _) => tasks.into_iter().partition(|task| match &task.content {
-                TaskType::OnceTask {
-                    status: OnceDateStatus::Expired,
-                    ..
-                } => false,
-                _ => true,
-            }),
-            (_, true, _, _, _) => tasks.into_iter().partition(|task| match &task.content {
-                TaskType::OnceTask { .. } => false,
-                _ => true,
-            }),
-            (_, _, true, _, _) => tasks.into_iter().partition(|task| match &task.content {
-                TaskType::MonthTask { .. } => false,
-                _ => true,
-            }),
-            (_, _, _, true, _) => tasks.into_iter().partition(|task| match &task.content {
-                TaskType::WeekTask { .. } => false,
-                _ => true,
-            }),
-            (_, _, _, _, true) => tasks.into_iter().partition(|task| match &task.content {
-                TaskType::ProgressTask { .. } => false,
-                _ => true,
-            }),
-            _ => return Err(anyhow!("{}", "error: Invalid filter!".bright_red())),
-        };
-    file.set_len(0)?;
-    let retained_tasks = id_reset(retained_tasks);
-    serde_json::to_writer_pretty(file, &retained_tasks)?;
-    let count = origin_len - retained_tasks.len();
     println!(
         "{}{}",
         "Specified tasks removed! count: ".bright_yellow(),
-        count.to_string().bright_yellow()
+        removed_tasks.len().to_string().bright_yellow()
     );
     removed_tasks
         .into_iter()
         .enumerate()
-        .for_each(|(index, task)| println!("{}: {}", index + 1, task));
+        .for_each(|(index, task)| println!("{}: {}", index + 1, task.format_info(now)));
 
     Ok(())
 }
 
-fn id_reset(tasks: Vec<Task>) -> Vec<Task> {
+/// Finds the task referred to by `id`: its position in the list (`3`), or
+/// its uid or a unique prefix of it of at least 4 characters (`1f2e3d4c`).
+/// An id that is both a position and the prefix of a uid, like `1234` in a
+/// list of over a thousand tasks, is ambiguous; the full uid always works.
+fn resolve_id(tasks: &[Task], id: &str) -> anyhow::Result<usize> {
+    let position = id
+        .parse::<usize>()
+        .ok()
+        .filter(|index| (1..=tasks.len()).contains(index))
+        .map(|index| index - 1);
+    let prefix = id.to_lowercase().replace('-', "");
+    let matched: Vec<usize> = if prefix.len() >= 4 {
+        tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, task)| task.uid.simple().to_string().starts_with(&prefix))
+            .map(|(index, _)| index)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    match (position, &matched[..]) {
+        (Some(index), []) | (None, &[index]) => Ok(index),
+        (Some(_), _) => Err(anyhow!(
+            "{}{}{}",
+            "error: Ambiguous task id, ".bright_red(),
+            id.bright_red(),
+            " is both an index and the start of a uid, please enter the full uid of the task"
+                .bright_red()
+        )),
+        (None, []) => Err(anyhow!(
+            "{}{}{}",
+            "error: Invalid task id! please enter an index between 1 and ".bright_red(),
+            tasks.len().to_string().bright_red(),
+            " or the uid of a task".bright_red()
+        )),
+        (None, _) => Err(anyhow!(
+            "{}{}",
+            "error: Ambiguous task uid, please enter more characters: ".bright_red(),
+            id.bright_red()
+        )),
+    }
+}
+
+fn id_reset(tasks: &mut [Task]) {
     tasks
-        .into_iter()
+        .iter_mut()
         .enumerate()
-        .map(|(index, mut task)| {
-            task.id = index + 1;
-            task
-        })
-        .collect()
+        .for_each(|(index, task)| task.id = index + 1);
 }
 
 pub fn list_tasks_by_filter(
-    expired: bool,
-    once_task: bool,
-    month_task: bool,
-    week_task: bool,
-    progress_task: bool,
+    store: &dyn TaskStore,
+    filter: TaskFilter,
+    clock: &Clock,
 ) -> anyhow::Result<()> {
-    let path = get_path()?;
-    let file = OpenOptions::new().read(true).open(path)?;
-    let tasks = collect_tasks(&file)?;
-    if tasks.is_empty() {
-        println!("{}", "warning: Task list is empty!".bright_yellow());
-        return Ok(());
-    }
-    let selected_tasks: Vec<Task> = match (expired, once_task, month_task, week_task, progress_task)
-    {
-        (true, _, _, _, _) => tasks
-            .into_iter()
-            .filter(|task| match &task.content {
-                TaskType::OnceTask {
-                    status: OnceDateStatus::Expired,
-                    ..
-                } => true,
-                _ => false,
-            })
-            .collect(),
-        (_, true, _, _, _) => tasks
-            .into_iter()
-            .filter(|task| match &task.content {
-                TaskType::OnceTask { .. } => true,
-                _ => false,
-            })
-            .collect(),
-        (_, _, true, _, _) => tasks
-            .into_iter()
-            .filter(|task| match &task.content {
-                TaskType::MonthTask { .. } => true,
-                _ => false,
-            })
-            .collect(),
-        (_, _, _, true, _) => tasks
-            .into_iter()
-            .filter(|task| match &task.content {
-                TaskType::WeekTask { .. } => true,
-                _ => false,
-            })
-            .collect(),
-        (_, _, _, _, true) => tasks
-            .into_iter()
-            .filter(|task| match &task.content {
-                TaskType::ProgressTask { .. } => true,
-                _ => false,
-            })
-            .collect(),
-        _ => tasks,
-    };
+    let now = clock.now();
+    let selected_tasks = store.query(filter, now)?;
     if selected_tasks.is_empty() {
-        println!(
-            "{}",
-            "warning: There are no tasks with selected type!".bright_yellow()
-        );
+        if let TaskFilter::All = filter {
+            println!("{}", "warning: Task list is empty!".bright_yellow());
+        } else {
+            println!(
+                "{}",
+                "warning: There are no tasks with selected type!".bright_yellow()
+            );
+        }
     } else {
         selected_tasks
             .into_iter()
             .enumerate()
-            .for_each(|(index, task)| println!("{}: {}", index + 1, task));
+            .for_each(|(index, task)| println!("{}: {}", index + 1, task.format_info(now)));
     }
 
     Ok(())
 }
 
-pub fn tasks_of_today() -> anyhow::Result<()> {
-    let path = get_path()?;
-    let file = OpenOptions::new().read(true).open(path)?;
-    let tasks = collect_tasks(&file)?;
-    if tasks.is_empty() {
-        println!("{}", "warning: Task list is empty!".bright_yellow());
-        return Ok(());
-    }
+/// Prints the tasks due today, the ones without a time of day first and the
+/// others by their time, then the once-time tasks whose reminder is due.
+pub fn tasks_of_today(store: &dyn TaskStore, config: &Config, clock: &Clock) -> anyhow::Result<()> {
+    let now = clock.now();
+    let today = now.date_naive();
     println!(
         "{} {} {} {}.",
-        date::get_greeting().bright_green(),
+        date::get_greeting(&config.greeting, clock).bright_green(),
         "Today is".bright_green(),
-        date::get_date().bright_green(),
-        date::get_weekday().to_string().bright_green()
+        today.format("%Y-%m-%d").to_string().bright_green(),
+        today.weekday().to_string().bright_green()
     );
-    let today_tasks: Vec<Task> = tasks
-        .into_iter()
-        .filter(|task| match &task.content {
-            TaskType::OnceTask {
-                status: OnceDateStatus::Ongoing,
-                ..
-            } => true,
-            TaskType::WeekTask { ongoing: true, .. } => true,
-            TaskType::MonthTask { ongoing: true, .. } => true,
-            _ => false,
-        })
-        .collect();
+    let mut today_tasks = store.query(TaskFilter::Today, now)?;
+    today_tasks.sort_by_key(|task| task.content.time_in(now.timezone()));
     if !today_tasks.is_empty() {
         println!(
             "{}",
@@ -464,13 +798,479 @@ pub fn tasks_of_today() -> anyhow::Result<()> {
         today_tasks
             .into_iter()
             .enumerate()
-            .for_each(|(index, task)| println!("{}: {}", index + 1, task));
+            .for_each(|(index, task)| println!("{}: {}", index + 1, task.format_info(now)));
     } else {
         println!(
             "{}",
             "Take a break! there are no tasks today!".bright_green()
         );
     };
+    let mut due_soon: Vec<((NaiveDate, Option<NaiveTime>), Task)> = store
+        .query(TaskFilter::Once, now)?
+        .into_iter()
+        .filter(|task| {
+            task.content.once_status(now) == Some(OnceDateStatus::DueSoon) && !task.is_done(now)
+        })
+        .filter_map(|task| Some((task.content.due_in(now.timezone())?, task)))
+        .collect();
+    due_soon.sort_by_key(|((date, time), _)| (*date, *time));
+    if !due_soon.is_empty() {
+        let color = Colors::current().due_soon.as_str();
+        println!("{}", "Due soon:".color(color));
+        due_soon
+            .into_iter()
+            .enumerate()
+            .for_each(|(index, ((date, _), task))| {
+                let days_left = match (date - today).num_days() {
+                    1 => "1 day left".to_string(),
+                    days => format!("{} days left", days),
+                };
+                println!(
+                    "{}: {} - {}",
+                    index + 1,
+                    task.format_info(now),
+                    days_left.color(color)
+                )
+            });
+    }
+
+    Ok(())
+}
+
+/// Lists the tasks due on each day from `from` to `to`, both included. The
+/// range defaults to the 7 days starting today.
+pub fn show_agenda(
+    store: &dyn TaskStore,
+    from: Option<&str>,
+    to: Option<&str>,
+    config: &Config,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let today = clock.today();
+    let from = match from {
+        Some(from) => date::parse_user_date(from, &config.date_format, today)?,
+        None => today,
+    };
+    let to = match to {
+        Some(to) => date::parse_user_date(to, &config.date_format, today)?,
+        None => from + Duration::days(AGENDA_DAYS - 1),
+    };
+    if from > to {
+        return Err(anyhow!(
+            "{}",
+            "error: Invalid date range, --from should not be after --to!".bright_red()
+        ));
+    }
+    let tasks = store.load()?;
+    let agenda = occurrences(&tasks, from, to, clock.zone());
+    if agenda.is_empty() {
+        println!(
+            "{}{}{}{}",
+            "warning: There are no tasks from ".bright_yellow(),
+            from.format("%Y-%m-%d").to_string().bright_yellow(),
+            " to ".bright_yellow(),
+            to.format("%Y-%m-%d").to_string().bright_yellow()
+        );
+        return Ok(());
+    }
+    print_agenda(agenda, clock.now());
+
+    Ok(())
+}
+
+/// Prints the tasks of each day with their state on that day: at `now` for
+/// today, and from the start of the day for the others.
+fn print_agenda(agenda: Vec<(NaiveDate, Vec<&Task>)>, now: DateTime<Tz>) {
+    agenda.into_iter().for_each(|(day, tasks)| {
+        let at = if day == now.date_naive() {
+            now
+        } else {
+            date::from_wall_clock(day.and_time(NaiveTime::MIN), now.timezone())
+        };
+        println!(
+            "{} {}",
+            day.format("%Y-%m-%d").to_string().bright_green(),
+            day.weekday().to_string().bright_green()
+        );
+        tasks
+            .into_iter()
+            .enumerate()
+            .for_each(|(index, task)| println!("  {}: {}", index + 1, task.format_info(at)));
+    });
+}
+
+/// Prints the month as a grid with the number of tasks due on each day.
+/// Today is green and the days with an expired once-time task are red.
+pub fn show_calendar(
+    store: &dyn TaskStore,
+    month: Option<&str>,
+    list: bool,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let now = clock.now();
+    let today = now.date_naive();
+    let first = match month {
+        Some(month) => date::parse_month(month)?,
+        None => today - Duration::days(today.day0().into()),
+    };
+    let weeks = date::month_weeks(first);
+    let last = weeks
+        .iter()
+        .flatten()
+        .flatten()
+        .last()
+        .copied()
+        .unwrap_or(first);
+    let tasks = store.load()?;
+    let agenda = occurrences(&tasks, first, last, now.timezone());
+
+    println!("{:^42}", first.format("%B %Y").to_string().bright_green());
+    println!(
+        "{}",
+        ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"]
+            .map(|weekday| format!("{:<6}", weekday))
+            .concat()
+            .bright_blue()
+    );
+    for week in weeks {
+        let line = week.map(|day| {
+            let Some(day) = day else {
+                return " ".repeat(6).normal();
+            };
+            let due = agenda
+                .iter()
+                .find(|(date, _)| *date == day)
+                .map(|(_, due)| due.as_slice())
+                .unwrap_or_default();
+            let cell = match due.len() {
+                0 => format!("{:<6}", format!("{:>2}", day.day())),
+                count => format!("{:<6}", format!("{:>2}({})", day.day(), count)),
+            };
+            let expired = due.iter().any(|task| {
+                task.content.once_status(now) == Some(OnceDateStatus::Expired) && !task.is_done(now)
+            });
+            if day == today {
+                cell.bright_green().bold()
+            } else if expired {
+                cell.bright_red()
+            } else if !due.is_empty() {
+                cell.bright_yellow()
+            } else {
+                cell.normal()
+            }
+        });
+        println!("{}", line.map(|cell| cell.to_string()).concat());
+    }
+    if list {
+        println!();
+        print_agenda(agenda, now);
+    }
+
+    Ok(())
+}
+
+const AGENDA_DAYS: i64 = 7;
+
+/// The days from `from` to `to` on which at least one task is due in `zone`,
+/// with those tasks.
+fn occurrences(
+    tasks: &[Task],
+    from: NaiveDate,
+    to: NaiveDate,
+    zone: Tz,
+) -> Vec<(NaiveDate, Vec<&Task>)> {
+    from.iter_days()
+        .take_while(|day| *day <= to)
+        .map(|day| {
+            let mut due = tasks
+                .iter()
+                .filter(|task| task.content.occurs_on(day, zone))
+                .collect::<Vec<_>>();
+            due.sort_by_key(|task| task.content.time_in(zone));
+            (day, due)
+        })
+        .filter(|(_, due)| !due.is_empty())
+        .collect()
+}
+
+pub fn undo(store: &mut dyn TaskStore) -> anyhow::Result<()> {
+    let mut undone = None;
+    store.exclusive(&mut |store| {
+        let mut history = store.load_history()?;
+        let Some(operation) = history.undo() else {
+            return Err(anyhow!(
+                "{}",
+                "error: There is nothing to undo!".bright_red()
+            ));
+        };
+        store.save(&operation.revert(&store.load()?)?)?;
+        undone = Some(operation.description.clone());
+        store.save_history(&history)
+    })?;
+    if let Some(description) = undone {
+        println!("{} {}", "Undone:".bright_yellow(), description);
+    }
+
+    Ok(())
+}
+
+pub fn redo(store: &mut dyn TaskStore) -> anyhow::Result<()> {
+    let mut redone = None;
+    store.exclusive(&mut |store| {
+        let mut history = store.load_history()?;
+        let Some(operation) = history.redo() else {
+            return Err(anyhow!(
+                "{}",
+                "error: There is nothing to redo!".bright_red()
+            ));
+        };
+        store.save(&operation.replay(&store.load()?)?)?;
+        redone = Some(operation.description.clone());
+        store.save_history(&history)
+    })?;
+    if let Some(description) = redone {
+        println!("{} {}", "Redone:".bright_green(), description);
+    }
+
+    Ok(())
+}
+
+pub fn show_history(store: &dyn TaskStore, clock: &Clock) -> anyhow::Result<()> {
+    let history = store.load_history()?;
+    if history.operations.is_empty() {
+        println!("{}", "warning: History is empty!".bright_yellow());
+        return Ok(());
+    }
+    history
+        .operations
+        .iter()
+        .enumerate()
+        .rev()
+        .for_each(|(index, operation)| {
+            let flag = if index < history.cursor {
+                "".normal()
+            } else {
+                " (undone)".bright_yellow()
+            };
+            println!(
+                "{}: {} - {}{}",
+                index + 1,
+                clock.format(&operation.time).bright_green(),
+                operation.description.bright_blue(),
+                flag
+            )
+        });
+
+    Ok(())
+}
+
+pub fn list_backups(store: &dyn TaskStore) -> anyhow::Result<()> {
+    let backups = store.backups().ok_or(anyhow!(
+        "{}",
+        "error: This journal has no backups!".bright_red()
+    ))?;
+    let paths = backups.list()?;
+    if paths.is_empty() {
+        println!("{}", "warning: There are no backups yet!".bright_yellow());
+        return Ok(());
+    }
+    println!(
+        "{}{}",
+        "The backups are kept in: ".bright_green(),
+        backups.dir().display().to_string().bright_green()
+    );
+    paths.iter().enumerate().for_each(|(index, path)| {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let count = match backup::read_backup(path) {
+            Ok(tasks) => format!("{} tasks", tasks.len()).normal(),
+            Err(_) => "invalid".bright_red(),
+        };
+        println!("{}: {} - {}", index + 1, name.bright_green(), count)
+    });
+
+    Ok(())
+}
+
+/// Replaces the tasks with the `n`-th newest backup, as an undoable change.
+pub fn restore_backup(store: &mut dyn TaskStore, n: usize) -> anyhow::Result<()> {
+    let tasks = store
+        .backups()
+        .ok_or(anyhow!(
+            "{}",
+            "error: This journal has no backups!".bright_red()
+        ))?
+        .read(n)?;
+    let count = tasks.len();
+    store.exclusive(&mut |store| {
+        // A journal that can't be read is replaced as well, but then there is
+        // nothing to record in the history to undo the restore with.
+        let history = match store.load() {
+            Ok(before) if before == tasks => return Ok(()),
+            Ok(before) => {
+                let mut history = store.load_history()?;
+                history.record(&format!("restore backup {}", n), &before, &tasks);
+                history
+            }
+            Err(_) => History::default(),
+        };
+        store.save(&tasks)?;
+        store.save_history(&history)
+    })?;
+    println!(
+        "{}{}",
+        "Backup restored! count: ".bright_green(),
+        count.to_string().bright_green()
+    );
+
+    Ok(())
+}
+
+/// Reports the problems in the journal. With `fix`, the tasks are renumbered,
+/// duplicate uids are replaced and the unusable entries are moved to a
+/// quarantine file next to the journal.
+pub fn doctor(store: &mut dyn TaskStore, fix: bool) -> anyhow::Result<()> {
+    let quarantine_path = store
+        .location()
+        .map(|path| path.with_extension("quarantine.json"));
+    store.exclusive(&mut |store| {
+        let entries = match store.load_raw() {
+            Ok(entries) => entries,
+            Err(e) if !fix => {
+                println!("{}", e);
+                println!(
+                    "{}",
+                    "Run `ttd doctor --fix` to move the journal to quarantine and start over."
+                        .bright_yellow()
+                );
+                return Ok(());
+            }
+            Err(e) => {
+                println!("{}", e);
+                let content = match store.location() {
+                    Some(path) => fs::read(path)?,
+                    None => Vec::new(),
+                };
+                let path = match (String::from_utf8(content), store.location()) {
+                    // A database is not text, so the whole file is copied next to it instead.
+                    (Err(_), Some(path)) => {
+                        let extension = path.extension().unwrap_or_default().to_string_lossy();
+                        let copy = path.with_extension(format!("quarantine.{}", extension));
+                        fs::copy(path, &copy)?;
+                        copy
+                    }
+                    (content, _) => {
+                        let content = content.unwrap_or_default();
+                        quarantine(quarantine_path.as_deref(), vec![Value::String(content)])?
+                    }
+                };
+                store.save(&[])?;
+                store.save_history(&History::default())?;
+                println!(
+                    "{}{}",
+                    "The journal was reset, its content was moved to: ".bright_green(),
+                    path.display().to_string().bright_green()
+                );
+                return Ok(());
+            }
+        };
+        let problems = doctor::diagnose(&entries);
+        if problems.is_empty() {
+            println!(
+                "{}{}",
+                "No problems found in the journal! count: ".bright_green(),
+                entries.len().to_string().bright_green()
+            );
+            return Ok(());
+        }
+        problems.iter().for_each(|problem| println!("{}", problem));
+        if !fix {
+            println!(
+                "{}",
+                "Run `ttd doctor --fix` to fix these problems and quarantine the broken tasks."
+                    .bright_yellow()
+            );
+            return Ok(());
+        }
+        let (tasks, quarantined) = doctor::repair(entries);
+        if !quarantined.is_empty() {
+            let count = quarantined.len();
+            let path = quarantine(quarantine_path.as_deref(), quarantined)?;
+            println!(
+                "{}{}{}",
+                count.to_string().bright_yellow(),
+                " broken tasks moved to: ".bright_yellow(),
+                path.display().to_string().bright_yellow()
+            );
+        }
+        // Broken tasks can't be kept in an operation, so when there are some
+        // the history is started over rather than left pointing at a journal
+        // that no longer exists.
+        let history = match store.load() {
+            Ok(before) if before == tasks => return Ok(()),
+            Ok(before) => {
+                let mut history = store.load_history()?;
+                history.record("doctor --fix", &before, &tasks);
+                history
+            }
+            Err(_) => History::default(),
+        };
+        store.save(&tasks)?;
+        store.save_history(&history)?;
+        println!(
+            "{}{}",
+            "Journal repaired! count: ".bright_green(),
+            tasks.len().to_string().bright_green()
+        );
+
+        Ok(())
+    })
+}
+
+/// Appends `entries` to the quarantine file, keeping what is already in it.
+fn quarantine(path: Option<&Path>, entries: Vec<Value>) -> anyhow::Result<PathBuf> {
+    let path = path.ok_or(anyhow!(
+        "{}",
+        "error: This journal has no place to quarantine broken tasks!".bright_red()
+    ))?;
+    let mut quarantined: Vec<Value> = match fs::read(path) {
+        Ok(content) => serde_json::from_slice(&content)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => Err(e)?,
+    };
+    quarantined.extend(entries);
+    fs::write(path, serde_json::to_vec_pretty(&quarantined)?)?;
+
+    Ok(path.to_path_buf())
+}
+
+/// Copies the tasks and history of `from` to `to`, holding both journals so
+/// that neither changes in between.
+pub fn migrate(from: &mut dyn TaskStore, to: &mut dyn TaskStore) -> anyhow::Result<()> {
+    let mut count = 0;
+    from.exclusive(&mut |from| {
+        let tasks = from.load()?;
+        // The operations still apply to the same tasks, so undo keeps working.
+        let history = from.load_history()?;
+        to.exclusive(&mut |to| {
+            to.save(&tasks)?;
+            to.save_history(&history)
+        })?;
+        count = tasks.len();
+        Ok(())
+    })?;
+    print!(
+        "{}{}",
+        "Journal migrated! count: ".bright_green(),
+        count.to_string().bright_green()
+    );
+    match to.location() {
+        Some(path) => println!(
+            "{}{}",
+            ", path: ".bright_green(),
+            path.display().to_string().bright_green()
+        ),
+        None => println!(),
+    }
 
     Ok(())
 }
@@ -478,15 +1278,500 @@ pub fn tasks_of_today() -> anyhow::Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::store::MemoryStore;
+    use chrono::Weekday;
 
-    #[test]
-    fn test_get_journal_file() {
-        let journal_file = get_path();
-        println!("Journal file: {:?}", journal_file);
+    /// Tuesday 2024-04-02, 14:30.
+    fn clock() -> Clock {
+        Clock::fixed(
+            NaiveDate::from_ymd_opt(2024, 4, 2)
+                .unwrap()
+                .and_hms_opt(14, 30, 0)
+                .unwrap(),
+        )
+    }
+
+    fn bookmark(text: &str) -> Task {
+        parse_task(
+            text.to_string(),
+            None,
+            None,
+            None,
+            Some("page 1".to_string()),
+            None,
+            None,
+            &Config::default(),
+            &clock(),
+        )
+        .unwrap()
     }
 
     #[test]
     fn test_handle_user_input() {
-        handle_user_input().unwrap();
+        handle_user_input(&mut MemoryStore::default(), &Config::default(), &clock()).unwrap();
+    }
+
+    #[test]
+    fn test_add_and_remove_reset_ids() {
+        let mut store = MemoryStore::default();
+        add_task(&mut store, bookmark("first"), &clock()).unwrap();
+        add_task(&mut store, bookmark("second"), &clock()).unwrap();
+        add_task(&mut store, bookmark("third"), &clock()).unwrap();
+        remove_task_by_id(&mut store, Some("1"), &clock()).unwrap();
+        let ids: Vec<usize> = store.load().unwrap().iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert!(remove_task_by_id(&mut store, Some("3"), &clock()).is_err());
+    }
+
+    #[test]
+    fn test_uid_survives_removal() {
+        let mut store = MemoryStore::default();
+        add_task(&mut store, bookmark("first"), &clock()).unwrap();
+        add_task(&mut store, bookmark("second"), &clock()).unwrap();
+        let uid = store.load().unwrap()[1].short_uid();
+        remove_task_by_id(&mut store, Some("1"), &clock()).unwrap();
+        update_bookmark(&mut store, &uid, "page 2".to_string(), &clock()).unwrap();
+        let tasks = store.load().unwrap();
+        assert_eq!(tasks[0].short_uid(), uid);
+        assert!(matches!(
+            &tasks[0].content,
+            TaskType::ProgressTask { progress, .. } if progress == "page 2"
+        ));
+        remove_task_by_id(&mut store, Some(&uid), &clock()).unwrap();
+        assert!(store.load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_id() {
+        let mut tasks: Vec<Task> = (0..1234).map(|_| bookmark("book")).collect();
+        id_reset(&mut tasks);
+        assert_eq!(resolve_id(&tasks, "7").unwrap(), 6);
+        let uid = tasks[9].uid.simple().to_string();
+        assert_eq!(resolve_id(&tasks, &uid[..6]).unwrap(), 9);
+
+        // An index that is also the start of a uid needs the full uid.
+        tasks[9].uid = "12345678-0000-4000-8000-000000000000".parse().unwrap();
+        assert!(resolve_id(&tasks, "1234").is_err());
+        assert_eq!(resolve_id(&tasks, "1233").unwrap(), 1232);
+        assert_eq!(resolve_id(&tasks, "12345678").unwrap(), 9);
+        let uid = tasks[9].uid.to_string();
+        assert_eq!(resolve_id(&tasks, &uid).unwrap(), 9);
+    }
+
+    #[test]
+    fn test_complete_task() {
+        let mut store = MemoryStore::default();
+        add_task(&mut store, bookmark("book"), &clock()).unwrap();
+        complete_task(&mut store, "1", &clock()).unwrap();
+        assert!(store.load().unwrap()[0].is_done(clock().now()));
+        assert!(complete_task(&mut store, "1", &clock()).is_err());
+        assert_eq!(
+            store.query(TaskFilter::Done, clock().now()).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_done_until_next_occurrence() {
+        let mut store = MemoryStore::default();
+        let gym = parse_task(
+            "gym".to_string(),
+            Some("tue".to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+            &Config::default(),
+            &clock(),
+        )
+        .unwrap();
+        add_task(&mut store, gym, &clock()).unwrap();
+        complete_task(&mut store, "1", &clock()).unwrap();
+        let today = clock().now();
+        let next_week = today + Duration::days(7);
+        assert!(store.query(TaskFilter::Today, today).unwrap().is_empty());
+        assert_eq!(
+            store
+                .query(TaskFilter::Done, today + Duration::days(3))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(store.query(TaskFilter::Today, next_week).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_once_time_in_another_zone() {
+        let tokyo = clock().in_zone(Tz::Asia__Tokyo);
+        let call = |date: &str| {
+            parse_task(
+                "call".to_string(),
+                None,
+                None,
+                Some(date.to_string()),
+                None,
+                None,
+                None,
+                &Config::default(),
+                &tokyo,
+            )
+            .unwrap()
+        };
+        // 18:00 in Tokyo is 11:00 in Paris.
+        let task = call("20240402T18:00");
+        let paris = |hour| {
+            Clock::fixed(
+                NaiveDate::from_ymd_opt(2024, 4, 2)
+                    .unwrap()
+                    .and_hms_opt(hour, 0, 0)
+                    .unwrap(),
+            )
+            .in_zone(Tz::Europe__Paris)
+            .now()
+        };
+        assert_eq!(
+            task.content.once_status(paris(10)),
+            Some(OnceDateStatus::Ongoing)
+        );
+        assert_eq!(
+            task.content.once_status(paris(12)),
+            Some(OnceDateStatus::Expired)
+        );
+        // 02:00 in Tokyo is still the day before in Paris.
+        let task = call("20240403T02:00");
+        let yesterday = NaiveDate::from_ymd_opt(2024, 4, 2).unwrap();
+        assert!(task.content.occurs_on(yesterday, Tz::Europe__Paris));
+        assert!(!task.content.occurs_on(yesterday, Tz::Asia__Tokyo));
+        assert_eq!(
+            task.content.time_in(Tz::Europe__Paris),
+            NaiveTime::from_hms_opt(19, 0, 0)
+        );
+        // A date without a time is the same day everywhere.
+        let task = call("20240403");
+        assert!(task
+            .content
+            .occurs_on(yesterday.succ_opt().unwrap(), Tz::Europe__Paris));
+    }
+
+    #[test]
+    fn test_remind() {
+        let mut config = Config::default();
+        let once = |date: &str, remind: Option<&str>, config: &Config| {
+            parse_task(
+                "report".to_string(),
+                None,
+                None,
+                Some(date.to_string()),
+                None,
+                None,
+                remind.map(str::to_string),
+                config,
+                &clock(),
+            )
+        };
+        let now = clock().now();
+        let task = once("20240405", Some("3d"), &config).unwrap();
+        assert_eq!(task.content.once_status(now), Some(OnceDateStatus::DueSoon));
+        let task = once("20240405", None, &config).unwrap();
+        assert_eq!(
+            task.content.once_status(now),
+            Some(OnceDateStatus::Upcoming)
+        );
+        config.remind = Some("1w".to_string());
+        let task = once("20240405", None, &config).unwrap();
+        assert_eq!(task.content.once_status(now), Some(OnceDateStatus::DueSoon));
+        assert!(once("20240405", Some("soon"), &config).is_err());
+        assert!(parse_task(
+            "gym".to_string(),
+            Some("tue".to_string()),
+            None,
+            None,
+            None,
+            None,
+            Some("1d".to_string()),
+            &config,
+            &clock(),
+        )
+        .is_err());
+
+        // Editing keeps the reminder unless a new one is given.
+        let mut store = MemoryStore::default();
+        add_task(&mut store, task, &clock()).unwrap();
+        let edit = TaskEdit {
+            text: Some("final report".to_string()),
+            ..Default::default()
+        };
+        edit_task(&mut store, "1", edit, &Config::default(), &clock()).unwrap();
+        let status = |store: &MemoryStore| store.load().unwrap()[0].content.once_status(now);
+        assert_eq!(status(&store), Some(OnceDateStatus::DueSoon));
+        let edit = TaskEdit {
+            remind: Some("1d".to_string()),
+            ..Default::default()
+        };
+        edit_task(&mut store, "1", edit, &Config::default(), &clock()).unwrap();
+        assert_eq!(status(&store), Some(OnceDateStatus::Upcoming));
+    }
+
+    #[test]
+    fn test_recurring_task() {
+        let mut store = MemoryStore::default();
+        let review = parse_task(
+            "review".to_string(),
+            None,
+            None,
+            None,
+            None,
+            Some("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU".to_string()),
+            None,
+            &Config::default(),
+            &clock(),
+        )
+        .unwrap();
+        add_task(&mut store, review, &clock()).unwrap();
+        let today = clock().now();
+        assert_eq!(store.query(TaskFilter::Recurring, today).unwrap().len(), 1);
+        complete_task(&mut store, "1", &clock()).unwrap();
+        assert!(store.query(TaskFilter::Today, today).unwrap().is_empty());
+        let next_week = today + Duration::days(7);
+        assert!(store
+            .query(TaskFilter::Today, next_week)
+            .unwrap()
+            .is_empty());
+        assert_eq!(store.query(TaskFilter::Done, next_week).unwrap().len(), 1);
+        let in_two_weeks = today + Duration::days(14);
+        assert_eq!(
+            store.query(TaskFilter::Today, in_two_weeks).unwrap().len(),
+            1
+        );
+
+        let edit = TaskEdit {
+            text: Some("retro".to_string()),
+            ..Default::default()
+        };
+        edit_task(&mut store, "1", edit, &Config::default(), &clock()).unwrap();
+        let task = &store.load().unwrap()[0];
+        assert!(task.content.occurs_on(in_two_weeks.date_naive(), Tz::UTC));
+        assert!(!task.content.occurs_on(next_week.date_naive(), Tz::UTC));
+    }
+
+    #[test]
+    fn test_occurrences() {
+        let config = Config::default();
+        let task = |text: &str, weekday: Option<&str>, day: Option<&str>, date: Option<&str>| {
+            let weekday = weekday.map(str::to_string);
+            let day = day.map(str::to_string);
+            let date = date.map(str::to_string);
+            parse_task(
+                text.to_string(),
+                weekday,
+                day,
+                date,
+                None,
+                None,
+                None,
+                &config,
+                &clock(),
+            )
+            .unwrap()
+        };
+        let tasks = vec![
+            task("gym", Some("fri"), None, None),
+            task("rent", None, Some("1"), None),
+            task("trip", None, None, Some("20240403")),
+            task("dentist", None, None, Some("20240405T10:00")),
+            task("swim", Some("fri@08:30"), None, None),
+            bookmark("book"),
+        ];
+        let from = clock().today();
+        let agenda = occurrences(&tasks, from, from + Duration::days(6), clock().zone());
+        let days: Vec<(String, Vec<&str>)> = agenda
+            .iter()
+            .map(|(day, due)| {
+                let texts = due.iter().map(|task| task.content.text()).collect();
+                (day.format("%m%d").to_string(), texts)
+            })
+            .collect();
+        assert_eq!(
+            days,
+            vec![
+                ("0403".to_string(), vec!["trip"]),
+                ("0405".to_string(), vec!["gym", "swim", "dentist"]),
+            ]
+        );
+        let agenda = occurrences(&tasks, from, from + Duration::days(29), clock().zone());
+        assert_eq!(agenda.len(), 6);
+        assert!(occurrences(&tasks, from, from.pred_opt().unwrap(), clock().zone()).is_empty());
+    }
+
+    #[test]
+    fn test_moved() {
+        let once = |text: &str, date: &str| {
+            parse_task(
+                text.to_string(),
+                None,
+                None,
+                Some(date.to_string()),
+                None,
+                None,
+                None,
+                &Config::default(),
+                &clock(),
+            )
+            .unwrap()
+        };
+        let expired = once("trip", "20240401");
+        assert!(!moved(&expired, &once("holiday", "20240401")));
+        assert!(moved(&expired, &once("trip", "20240401@09:00")));
+        assert!(moved(&expired, &once("trip", "20240331")));
+        assert!(moved(&bookmark("trip"), &expired));
+    }
+
+    #[test]
+    fn test_edit_task() {
+        let mut store = MemoryStore::default();
+        let config = Config::default();
+        add_task(&mut store, bookmark("book"), &clock()).unwrap();
+        let uid = store.load().unwrap()[0].uid;
+        let edit = TaskEdit {
+            text: Some("novel".to_string()),
+            ..Default::default()
+        };
+        edit_task(&mut store, "1", edit, &config, &clock()).unwrap();
+        let edit = TaskEdit {
+            weekday: Some("fri,mon@9:00".to_string()),
+            ..Default::default()
+        };
+        edit_task(&mut store, "1", edit, &config, &clock()).unwrap();
+        let edit = TaskEdit {
+            text: Some("novel".to_string()),
+            ..Default::default()
+        };
+        edit_task(&mut store, "1", edit, &config, &clock()).unwrap();
+        let task = &store.load().unwrap()[0];
+        assert_eq!(task.uid, uid);
+        assert!(matches!(
+            &task.content,
+            TaskType::WeekTask { text, weekdays, time }
+                if text == "novel"
+                    && weekdays == &[Weekday::Mon, Weekday::Fri]
+                    && *time == NaiveTime::from_hms_opt(9, 0, 0)
+        ));
+
+        let edit = TaskEdit {
+            day: Some("3".to_string()),
+            progress: Some("page 1".to_string()),
+            ..Default::default()
+        };
+        assert!(edit_task(&mut store, "1", edit, &config, &clock()).is_err());
+        let edit = TaskEdit {
+            weekday: Some("someday".to_string()),
+            ..Default::default()
+        };
+        assert!(edit_task(&mut store, "1", edit, &config, &clock()).is_err());
+    }
+
+    #[test]
+    fn test_edit_keeps_time_of_day() {
+        let mut store = MemoryStore::default();
+        let config = Config::default();
+        let tokyo = clock().in_zone(Tz::Asia__Tokyo);
+        let call = parse_task(
+            "call".to_string(),
+            None,
+            None,
+            Some("20240405@09:30".to_string()),
+            None,
+            None,
+            None,
+            &config,
+            &tokyo,
+        )
+        .unwrap();
+        add_task(&mut store, call, &tokyo).unwrap();
+        let edit = TaskEdit {
+            date: Some("20240406".to_string()),
+            ..Default::default()
+        };
+        edit_task(&mut store, "1", edit, &config, &clock()).unwrap();
+        assert!(matches!(
+            &store.load().unwrap()[0].content,
+            TaskType::OnceTask { date, time, zone, .. }
+                if *date == NaiveDate::from_ymd_opt(2024, 4, 6).unwrap()
+                    && *time == NaiveTime::from_hms_opt(9, 30, 0)
+                    && *zone == Some(Tz::Asia__Tokyo)
+        ));
+
+        let edit = TaskEdit {
+            weekday: Some("fri".to_string()),
+            ..Default::default()
+        };
+        edit_task(&mut store, "1", edit, &config, &clock()).unwrap();
+        let edit = TaskEdit {
+            day: Some("15".to_string()),
+            ..Default::default()
+        };
+        edit_task(&mut store, "1", edit, &config, &clock()).unwrap();
+        assert!(matches!(
+            &store.load().unwrap()[0].content,
+            TaskType::MonthTask { day: 15, time, .. } if *time == NaiveTime::from_hms_opt(9, 30, 0)
+        ));
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut store = MemoryStore::default();
+        add_task(&mut store, bookmark("first"), &clock()).unwrap();
+        add_task(&mut store, bookmark("second"), &clock()).unwrap();
+        clear_tasks(&mut store).unwrap();
+        assert!(store.load().unwrap().is_empty());
+
+        undo(&mut store).unwrap();
+        assert_eq!(store.load().unwrap().len(), 2);
+        undo(&mut store).unwrap();
+        assert_eq!(store.load().unwrap().len(), 1);
+        redo(&mut store).unwrap();
+        assert_eq!(store.load().unwrap().len(), 2);
+
+        remove_task_by_id(&mut store, Some("1"), &clock()).unwrap();
+        assert!(redo(&mut store).is_err());
+        undo(&mut store).unwrap();
+        undo(&mut store).unwrap();
+        undo(&mut store).unwrap();
+        assert!(store.load().unwrap().is_empty());
+        assert!(undo(&mut store).is_err());
+    }
+
+    #[test]
+    fn test_undo_refuses_changed_journal() {
+        let mut store = MemoryStore::default();
+        add_task(&mut store, bookmark("first"), &clock()).unwrap();
+        store.save(&[]).unwrap();
+        assert!(undo(&mut store).is_err());
+        assert!(store.load().unwrap().is_empty());
+        assert_eq!(store.load_history().unwrap().cursor, 1);
+    }
+
+    #[test]
+    fn test_migrate_keeps_history() {
+        let mut from = MemoryStore::default();
+        add_task(&mut from, bookmark("first"), &clock()).unwrap();
+        let mut to = MemoryStore::default();
+        migrate(&mut from, &mut to).unwrap();
+        undo(&mut to).unwrap();
+        assert!(to.load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_update_bookmark() {
+        let mut store = MemoryStore::default();
+        add_task(&mut store, bookmark("book"), &clock()).unwrap();
+        update_bookmark(&mut store, "1", "page 99".to_string(), &clock()).unwrap();
+        let tasks = store.load().unwrap();
+        assert!(matches!(
+            &tasks[0].content,
+            TaskType::ProgressTask { progress, .. } if progress == "page 99"
+        ));
+        assert!(update_bookmark(&mut store, "0", "page 1".to_string(), &clock()).is_err());
     }
 }
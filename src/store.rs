@@ -0,0 +1,719 @@
+use crate::{
+    backup::Backups,
+    history::History,
+    schema,
+    task::{OnceDateStatus, Task, TaskType},
+};
+use anyhow::anyhow;
+use chrono::DateTime;
+use chrono_tz::Tz;
+use colored::Colorize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{self, File, TryLockError},
+    io::{BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The formats the journal can be kept in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+impl Backend {
+    fn extension(&self) -> &'static str {
+        match self {
+            Backend::Json => "json",
+            Backend::Sqlite => "sqlite",
+        }
+    }
+}
+
+/// Where the journal lives and which backend keeps it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Journal {
+    pub path: PathBuf,
+    pub backend: Backend,
+}
+
+impl Journal {
+    /// Finds the journal for `path`. A `.sqlite` path is opened with SQLite;
+    /// any other path is a JSON journal unless it has been migrated to SQLite,
+    /// in which case the database next to it (same name, `.sqlite` extension) is used.
+    /// Likewise, a missing `.sqlite` path whose JSON journal exists has been
+    /// migrated back to JSON.
+    pub fn locate(path: impl Into<PathBuf>) -> Journal {
+        let path = path.into();
+        let sqlite_path = path.with_extension(Backend::Sqlite.extension());
+        let json_path = path.with_extension(Backend::Json.extension());
+        let migrated_to_json = !sqlite_path.exists() && json_path.exists();
+        if path == sqlite_path && migrated_to_json {
+            Journal {
+                path: json_path,
+                backend: Backend::Json,
+            }
+        } else if path == sqlite_path || (cfg!(feature = "sqlite") && sqlite_path.exists()) {
+            Journal {
+                path: sqlite_path,
+                backend: Backend::Sqlite,
+            }
+        } else {
+            Journal {
+                path,
+                backend: Backend::Json,
+            }
+        }
+    }
+
+    /// The journal in the home directory (`~/.ttd.json`).
+    pub fn locate_default() -> anyhow::Result<Journal> {
+        Ok(Journal::locate(default_path()?))
+    }
+
+    /// The same journal kept by another backend.
+    pub fn with_backend(&self, backend: Backend) -> Journal {
+        Journal {
+            path: self.path.with_extension(backend.extension()),
+            backend,
+        }
+    }
+
+    /// Opens the journal, keeping the `keep_backups` most recent versions of it.
+    pub fn open(&self, keep_backups: usize) -> anyhow::Result<Box<dyn TaskStore>> {
+        match self.backend {
+            Backend::Json => {
+                let mut store = JsonStore::new(&self.path).with_backups(keep_backups);
+                store.upgrade()?;
+                Ok(Box::new(store))
+            }
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => Ok(Box::new(
+                crate::sqlite::SqliteStore::open(&self.path)?.with_backups(keep_backups),
+            )),
+            #[cfg(not(feature = "sqlite"))]
+            Backend::Sqlite => Err(anyhow!(
+                "{}",
+                "error: ttd was built without SQLite support, please rebuild it with `--features sqlite`."
+                    .bright_red()
+            )),
+        }
+    }
+}
+
+/// Selects which tasks a query returns.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskFilter {
+    All,
+    Expired,
+    Once,
+    Month,
+    Week,
+    Progress,
+    Recurring,
+    Today,
+    Done,
+}
+
+impl TaskFilter {
+    /// Builds a filter from the flags accepted by `ttd l` and `ttd r`.
+    pub fn from_flags(
+        expired: bool,
+        once_task: bool,
+        month_task: bool,
+        week_task: bool,
+        progress_task: bool,
+        recurring_task: bool,
+        done: bool,
+    ) -> TaskFilter {
+        match (
+            expired,
+            once_task,
+            month_task,
+            week_task,
+            progress_task,
+            recurring_task,
+            done,
+        ) {
+            (true, _, _, _, _, _, _) => TaskFilter::Expired,
+            (_, true, _, _, _, _, _) => TaskFilter::Once,
+            (_, _, true, _, _, _, _) => TaskFilter::Month,
+            (_, _, _, true, _, _, _) => TaskFilter::Week,
+            (_, _, _, _, true, _, _) => TaskFilter::Progress,
+            (_, _, _, _, _, true, _) => TaskFilter::Recurring,
+            (_, _, _, _, _, _, true) => TaskFilter::Done,
+            _ => TaskFilter::All,
+        }
+    }
+
+    /// Whether `task` is selected, judging its state at `now`.
+    pub fn matches(&self, task: &Task, now: DateTime<Tz>) -> bool {
+        let today = now.date_naive();
+        match self {
+            TaskFilter::All => true,
+            TaskFilter::Expired => {
+                task.content.once_status(now) == Some(OnceDateStatus::Expired) && !task.is_done(now)
+            }
+            TaskFilter::Once => matches!(task.content, TaskType::OnceTask { .. }),
+            TaskFilter::Month => matches!(task.content, TaskType::MonthTask { .. }),
+            TaskFilter::Week => matches!(task.content, TaskType::WeekTask { .. }),
+            TaskFilter::Progress => matches!(task.content, TaskType::ProgressTask { .. }),
+            TaskFilter::Recurring => matches!(task.content, TaskType::RecurringTask { .. }),
+            TaskFilter::Today => {
+                task.content.occurs_on(today, now.timezone()) && !task.is_done(now)
+            }
+            TaskFilter::Done => task.is_done(now),
+        }
+    }
+}
+
+/// A place where the task list is kept.
+///
+/// Implementors provide plain reads and writes plus `exclusive`; `query` and
+/// `mutate` are built on top of them but may be overridden by backends that can do better.
+pub trait TaskStore {
+    /// Reads every task from the store.
+    fn load(&self) -> anyhow::Result<Vec<Task>>;
+
+    /// Reads every task as plain JSON, without checking that it is a valid task.
+    fn load_raw(&self) -> anyhow::Result<Vec<Value>> {
+        self.load()?
+            .iter()
+            .map(|task| Ok(serde_json::to_value(task)?))
+            .collect()
+    }
+
+    /// Replaces the content of the store with `tasks`.
+    fn save(&mut self, tasks: &[Task]) -> anyhow::Result<()>;
+
+    fn load_history(&self) -> anyhow::Result<History>;
+
+    fn save_history(&mut self, history: &History) -> anyhow::Result<()>;
+
+    /// Runs `f` while no other process can write to the store.
+    fn exclusive(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn TaskStore) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()>;
+
+    /// Reads the tasks selected by `filter` at `now`.
+    fn query(&self, filter: TaskFilter, now: DateTime<Tz>) -> anyhow::Result<Vec<Task>> {
+        Ok(self
+            .load()?
+            .into_iter()
+            .filter(|task| filter.matches(task, now))
+            .collect())
+    }
+
+    /// Loads the task list, applies `f` to it and saves the result, recording
+    /// the change in the history under `description`.
+    /// Nothing is written if `f` returns an error or leaves the list unchanged.
+    fn mutate(
+        &mut self,
+        description: &str,
+        f: &mut dyn FnMut(&mut Vec<Task>) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        self.exclusive(&mut |store| {
+            let mut tasks = store.load()?;
+            let before = tasks.clone();
+            f(&mut tasks)?;
+            if tasks == before {
+                return Ok(());
+            }
+            store.save(&tasks)?;
+            let mut history = store.load_history()?;
+            history.record(description, &before, &tasks);
+            store.save_history(&history)
+        })
+    }
+
+    /// The file backing the store, if there is one.
+    fn location(&self) -> Option<&Path> {
+        None
+    }
+
+    /// The backups taken before the store is overwritten, if it keeps any.
+    fn backups(&self) -> Option<&Backups> {
+        None
+    }
+}
+
+/// Keeps the task list as a pretty printed JSON array in a single file.
+///
+/// `exclusive` holds an advisory lock on a `.lock` file next to the journal,
+/// so concurrent `ttd` processes never overwrite each other's changes.
+/// The history is kept in a `.history.json` file next to it.
+pub struct JsonStore {
+    path: PathBuf,
+    lock_timeout: Duration,
+    backups: Option<Backups>,
+}
+
+impl JsonStore {
+    pub fn new(path: impl Into<PathBuf>) -> JsonStore {
+        JsonStore {
+            path: path.into(),
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            backups: None,
+        }
+    }
+
+    /// Keeps the `keep` most recent versions of the journal as backups.
+    pub fn with_backups(mut self, keep: usize) -> JsonStore {
+        self.backups = Some(Backups::for_journal(&self.path, keep));
+        self
+    }
+
+    /// Sets how long `exclusive` waits for another process to release the journal.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> JsonStore {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    fn lock(&self) -> anyhow::Result<JournalLock> {
+        JournalLock::acquire(&lock_path(&self.path), self.lock_timeout)
+    }
+
+    fn history_path(&self) -> PathBuf {
+        self.path.with_extension("history.json")
+    }
+
+    /// Rewrites a journal written by an older ttd in the current format, the
+    /// way the SQLite backend upgrades its database when it is opened, so what
+    /// the migrations make up, like the uids of old tasks, stays the same from
+    /// one run to the next. A journal that can't be upgraded is left as it is
+    /// for `ttd doctor`.
+    pub fn upgrade(&mut self) -> anyhow::Result<()> {
+        if !self.path.exists() || is_current(&self.path) {
+            return Ok(());
+        }
+        let _lock = self.lock()?;
+        if is_current(&self.path) {
+            return Ok(());
+        }
+        match self.load() {
+            Ok(tasks) => self.save(&tasks),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+/// Whether the journal at `path` is of the current version. One that can't be
+/// read counts as current, since upgrading it would fail too.
+fn is_current(path: &Path) -> bool {
+    read_journal(path)
+        .and_then(|journal| schema::version_of(&journal))
+        .map_or(true, |version| version == schema::VERSION)
+}
+
+impl TaskStore for JsonStore {
+    fn load(&self) -> anyhow::Result<Vec<Task>> {
+        schema::from_value(read_journal(&self.path)?)
+    }
+
+    fn load_raw(&self) -> anyhow::Result<Vec<Value>> {
+        schema::upgrade(read_journal(&self.path)?)
+    }
+
+    /// Backs up the current journal, then replaces it. Nothing is written
+    /// if the content has not changed.
+    fn save(&mut self, tasks: &[Task]) -> anyhow::Result<()> {
+        let content = schema::to_json(tasks)?;
+        let old_content = match fs::read(&self.path) {
+            Ok(old_content) => old_content,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => Err(e)?,
+        };
+        if content == old_content {
+            return Ok(());
+        }
+        if let (Some(backups), false) = (&self.backups, old_content.is_empty()) {
+            backups.create(&old_content)?;
+        }
+        write_atomic(&self.path, &content)
+    }
+
+    fn load_history(&self) -> anyhow::Result<History> {
+        read_json(&self.history_path())
+    }
+
+    fn save_history(&mut self, history: &History) -> anyhow::Result<()> {
+        write_atomic(&self.history_path(), &serde_json::to_vec_pretty(history)?)
+    }
+
+    fn exclusive(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn TaskStore) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let _lock = self.lock()?;
+        f(self)
+    }
+
+    fn location(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn backups(&self) -> Option<&Backups> {
+        self.backups.as_ref()
+    }
+}
+
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An exclusive advisory lock on the journal, released when dropped.
+struct JournalLock {
+    _file: File,
+}
+
+impl JournalLock {
+    fn acquire(path: &Path, timeout: Duration) -> anyhow::Result<JournalLock> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let start = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(JournalLock { _file: file }),
+                Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                    thread::sleep(LOCK_RETRY_INTERVAL)
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(anyhow!(
+                        "{}{}",
+                        "error: The journal is locked by another ttd process, please try again later. lock file: "
+                            .bright_red(),
+                        path.display().to_string().bright_red()
+                    ))
+                }
+                Err(TryLockError::Error(e)) => Err(e)?,
+            }
+        }
+    }
+}
+
+/// Keeps the task list in memory, useful for tests and for embedding.
+#[derive(Default)]
+pub struct MemoryStore {
+    tasks: Vec<Task>,
+    history: History,
+}
+
+impl MemoryStore {
+    pub fn new(tasks: Vec<Task>) -> MemoryStore {
+        MemoryStore {
+            tasks,
+            history: History::default(),
+        }
+    }
+}
+
+impl TaskStore for MemoryStore {
+    fn load(&self) -> anyhow::Result<Vec<Task>> {
+        Ok(self.tasks.clone())
+    }
+
+    fn save(&mut self, tasks: &[Task]) -> anyhow::Result<()> {
+        self.tasks = tasks.to_vec();
+
+        Ok(())
+    }
+
+    fn load_history(&self) -> anyhow::Result<History> {
+        Ok(self.history.clone())
+    }
+
+    fn save_history(&mut self, history: &History) -> anyhow::Result<()> {
+        self.history = history.clone();
+
+        Ok(())
+    }
+
+    fn exclusive(
+        &mut self,
+        f: &mut dyn FnMut(&mut dyn TaskStore) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        f(self)
+    }
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+/// Reads a journal file of any version, see [`schema`]. A missing or empty
+/// file is an empty journal.
+fn read_journal(path: &Path) -> anyhow::Result<Value> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Value::Array(Vec::new())),
+        Err(e) => Err(e)?,
+    };
+    match serde_json::from_slice(&content) {
+        Ok(journal) => Ok(journal),
+        Err(e) if e.is_eof() => Ok(Value::Array(Vec::new())),
+        Err(e) => Err(anyhow!(
+            "{}{}\n{}",
+            "error: The journal is not valid JSON: ".bright_red(),
+            path.display().to_string().bright_red(),
+            e
+        )),
+    }
+}
+
+/// Reads a JSON file, falling back to the default value if it is missing or empty.
+fn read_json<T: DeserializeOwned + Default>(path: &Path) -> anyhow::Result<T> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => Err(e)?,
+    };
+    match serde_json::from_reader(BufReader::new(file)) {
+        Ok(value) => Ok(value),
+        Err(e) if e.is_eof() => Ok(T::default()),
+        Err(e) => Err(e)?,
+    }
+}
+
+/// Writes `content` to a temporary file next to `path`, syncs it to disk and
+/// renames it over `path`, so a crash never leaves the file half-written.
+fn write_atomic(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let tmp_path = tmp_path(path);
+    let result = write_synced(&tmp_path, content).and_then(|_| {
+        fs::rename(&tmp_path, path)?;
+        sync_parent_dir(path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
+fn write_synced(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> anyhow::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all()?,
+        _ => File::open(".")?.sync_all()?,
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
+
+fn default_path() -> anyhow::Result<PathBuf> {
+    home::home_dir()
+        .map(|mut path| {
+            path.push(".ttd.json");
+            path
+        })
+        .ok_or(anyhow!("Could not find home directory.".bright_red()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::Clock;
+
+    #[test]
+    fn test_get_journal_file() {
+        let journal_file = default_path();
+        println!("Journal file: {:?}", journal_file);
+    }
+
+    #[test]
+    fn test_locate_journal() {
+        let journal = Journal::locate("/tmp/ttd-missing/work.sqlite");
+        assert_eq!(journal.backend, Backend::Sqlite);
+        let journal = Journal::locate("/tmp/ttd-missing/.ttd.json");
+        assert_eq!(journal.backend, Backend::Json);
+        assert_eq!(
+            journal.with_backend(Backend::Sqlite).path,
+            PathBuf::from("/tmp/ttd-missing/.ttd.sqlite")
+        );
+
+        // A database migrated back to JSON is found through its old path.
+        let path = temp_journal("locate").with_file_name("work.json");
+        fs::write(&path, "[]").unwrap();
+        let journal = Journal::locate(path.with_extension("sqlite"));
+        assert_eq!(journal.backend, Backend::Json);
+        assert_eq!(journal.path, path);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    fn temp_journal(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ttd-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(".ttd.json")
+    }
+
+    #[test]
+    fn test_json_store_atomic_save() {
+        let path = temp_journal("atomic");
+        let mut store = JsonStore::new(&path);
+        let tasks = vec![Task::build(
+            TaskType::ProgressTask {
+                text: "book".to_string(),
+                progress: "page 1".to_string(),
+            },
+            &Clock::system(),
+        )];
+        store.save(&tasks).unwrap();
+        store.save(&tasks).unwrap();
+        assert_eq!(store.load().unwrap().len(), 1);
+        assert!(!tmp_path(&path).exists());
+        let entries = fs::read_dir(path.parent().unwrap()).unwrap().count();
+        assert_eq!(entries, 1);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_json_store_backups() {
+        let path = temp_journal("backups");
+        let mut store = JsonStore::new(&path).with_backups(2);
+        let mut tasks = Vec::new();
+        for i in 0..4 {
+            tasks.push(Task::build(
+                TaskType::ProgressTask {
+                    text: i.to_string(),
+                    progress: "page 1".to_string(),
+                },
+                &Clock::system(),
+            ));
+            store.save(&tasks).unwrap();
+        }
+        store.save(&tasks).unwrap();
+        let backups = store.backups().unwrap();
+        assert_eq!(backups.list().unwrap().len(), 2);
+        assert_eq!(backups.read(1).unwrap().len(), 3);
+        assert_eq!(backups.read(2).unwrap().len(), 2);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_json_store_upgrade_keeps_uids() {
+        let path = temp_journal("upgrade");
+        let legacy = r#"[{"id":1,"created_at":"2024-04-01 08:00:00","completed_at":null,
+            "content":{"ProgressTask":{"text":"book","progress":"page 1"}}}]"#;
+        fs::write(&path, legacy).unwrap();
+        let journal = Journal::locate(&path);
+        let first = journal.open(0).unwrap().load().unwrap();
+        let second = journal.open(0).unwrap().load().unwrap();
+        assert_eq!(first[0].uid, second[0].uid);
+        assert!(is_current(&path));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_json_store_lock_timeout() {
+        let path = temp_journal("lock");
+        let mut store = JsonStore::new(&path).with_lock_timeout(Duration::from_millis(100));
+        let held = JournalLock::acquire(&lock_path(&path), Duration::ZERO).unwrap();
+        assert!(store.mutate("noop", &mut |_| Ok(())).is_err());
+        drop(held);
+        assert!(store.mutate("noop", &mut |_| Ok(())).is_ok());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_memory_store_mutate() {
+        let mut store = MemoryStore::default();
+        store
+            .mutate("add book", &mut |tasks| {
+                tasks.push(Task::build(
+                    TaskType::ProgressTask {
+                        text: "book".to_string(),
+                        progress: "page 1".to_string(),
+                    },
+                    &Clock::system(),
+                ));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(store.load().unwrap().len(), 1);
+
+        assert_eq!(store.load_history().unwrap().operations.len(), 1);
+
+        let result = store.mutate("clear", &mut |tasks| {
+            tasks.clear();
+            Err(anyhow!("abort"))
+        });
+        assert!(result.is_err());
+        assert_eq!(store.load().unwrap().len(), 1);
+        assert_eq!(store.load_history().unwrap().operations.len(), 1);
+    }
+
+    #[test]
+    fn test_query_by_filter() {
+        let store = MemoryStore::new(vec![
+            Task::build(
+                TaskType::ProgressTask {
+                    text: "book".to_string(),
+                    progress: "page 1".to_string(),
+                },
+                &Clock::system(),
+            ),
+            Task::build(
+                TaskType::OnceTask {
+                    text: "trip".to_string(),
+                    date: chrono::NaiveDate::from_ymd_opt(2024, 4, 2).unwrap(),
+                    time: None,
+                    zone: None,
+                    remind: None,
+                },
+                &Clock::system(),
+            ),
+            Task::build(
+                TaskType::OnceTask {
+                    text: "call".to_string(),
+                    date: chrono::NaiveDate::from_ymd_opt(2024, 4, 2).unwrap(),
+                    time: chrono::NaiveTime::from_hms_opt(14, 0, 0),
+                    zone: None,
+                    remind: None,
+                },
+                &Clock::system(),
+            ),
+        ]);
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 4, 2).unwrap();
+        let morning = Clock::fixed(today.and_hms_opt(9, 0, 0).unwrap()).now();
+        let afternoon = Clock::fixed(today.and_hms_opt(15, 0, 0).unwrap()).now();
+        let tomorrow = morning + chrono::Duration::days(1);
+        assert_eq!(store.query(TaskFilter::All, morning).unwrap().len(), 3);
+        assert_eq!(store.query(TaskFilter::Today, afternoon).unwrap().len(), 2);
+        assert_eq!(store.query(TaskFilter::Expired, morning).unwrap().len(), 0);
+        assert_eq!(
+            store.query(TaskFilter::Expired, afternoon).unwrap().len(),
+            1
+        );
+        assert_eq!(store.query(TaskFilter::Expired, tomorrow).unwrap().len(), 2);
+        assert_eq!(store.query(TaskFilter::Week, morning).unwrap().len(), 0);
+    }
+}
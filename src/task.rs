@@ -1,123 +1,343 @@
-use crate::date;
+use crate::{
+    config::Colors,
+    date::{self, Clock},
+    rrule::Rule,
+};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Task {
+    /// Position of the task in the list, renumbered after every removal.
     pub id: usize,
-    pub created_at: String,
+    /// Identifier that never changes; tasks saved before it existed get one on load.
+    #[serde(default = "Uuid::new_v4")]
+    pub uid: Uuid,
+    #[serde(deserialize_with = "date::deserialize_timestamp")]
+    pub created_at: DateTime<Utc>,
+    /// When the task was last marked as done.
+    #[serde(
+        default,
+        deserialize_with = "date::deserialize_optional_timestamp",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub completed_at: Option<DateTime<Utc>>,
     pub content: TaskType,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum TaskType {
+    /// Repeats on each of `weekdays`, kept in order from Monday without duplicates.
     WeekTask {
         text: String,
-        weekday: String,
-        ongoing: bool,
+        #[serde(with = "date::journal_weekdays")]
+        weekdays: Vec<Weekday>,
+        /// Time of day the task is due at, stored as `HH:MM`.
+        #[serde(
+            default,
+            with = "date::time_of_day",
+            skip_serializing_if = "Option::is_none"
+        )]
+        time: Option<NaiveTime>,
     },
 
+    /// Repeats on `day` of each month, counted from the end when negative.
     MonthTask {
         text: String,
-        day: usize,
-        ongoing: bool,
+        day: i32,
+        #[serde(default)]
+        overflow: MonthOverflow,
+        /// Time of day the task is due at, stored as `HH:MM`.
+        #[serde(
+            default,
+            with = "date::time_of_day",
+            skip_serializing_if = "Option::is_none"
+        )]
+        time: Option<NaiveTime>,
     },
 
     OnceTask {
         text: String,
-        date: String,
-        status: OnceDateStatus,
+        #[serde(with = "date::journal_date")]
+        date: NaiveDate,
+        /// Time of day the task is due at, stored as `HH:MM`.
+        #[serde(
+            default,
+            with = "date::time_of_day",
+            skip_serializing_if = "Option::is_none"
+        )]
+        time: Option<NaiveTime>,
+        /// The zone whose wall clock `date` and `time` are on, set for tasks
+        /// with a time so they stay due at the same moment in another zone.
+        #[serde(
+            default,
+            with = "date::time_zone",
+            skip_serializing_if = "Option::is_none"
+        )]
+        zone: Option<Tz>,
+        /// How many days before `date` the task is due soon.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        remind: Option<u32>,
     },
 
     ProgressTask {
         text: String,
         progress: String,
     },
+
+    RecurringTask {
+        text: String,
+        rule: Rule,
+    },
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// What a monthly task does in the months that don't have its day, e.g. the 31st in April.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MonthOverflow {
+    /// Falls on the last day of the month instead.
+    #[default]
+    Clamp,
+    /// Doesn't happen that month.
+    Skip,
+    /// Falls on the 1st of the next month instead.
+    Roll,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum OnceDateStatus {
     Expired,
+    /// Within the lead time of the task's reminder, before its date.
+    DueSoon,
     Upcoming,
     Ongoing,
 }
 
+impl TaskType {
+    pub fn text(&self) -> &str {
+        match self {
+            TaskType::WeekTask { text, .. }
+            | TaskType::MonthTask { text, .. }
+            | TaskType::OnceTask { text, .. }
+            | TaskType::ProgressTask { text, .. }
+            | TaskType::RecurringTask { text, .. } => text,
+        }
+    }
+
+    /// Whether the task is due on `day`: one of its weekdays, its monthday, its date
+    /// or one of the days of its rule. The date of a once-time task is the one
+    /// it falls on in `zone`.
+    pub fn occurs_on(&self, day: NaiveDate, zone: Tz) -> bool {
+        match self {
+            TaskType::WeekTask { weekdays, .. } => date::weekday_check(weekdays, day),
+            TaskType::MonthTask {
+                day: monthday,
+                overflow,
+                ..
+            } => date::day_check(*monthday, *overflow, day),
+            TaskType::OnceTask { .. } => self.due_in(zone).is_some_and(|(date, _)| date == day),
+            TaskType::ProgressTask { .. } => false,
+            TaskType::RecurringTask { rule, .. } => rule.occurs_on(day),
+        }
+    }
+
+    /// Where the date of a once-time task stands at `now`.
+    pub fn once_status(&self, now: DateTime<Tz>) -> Option<OnceDateStatus> {
+        let TaskType::OnceTask { remind, .. } = self else {
+            return None;
+        };
+        let (date, time) = self.due_in(now.timezone())?;
+        Some(date::date_check(date, time, *remind, now.naive_local()))
+    }
+
+    /// The date and time of day of a once-time task on the wall clock of `zone`.
+    pub fn due_in(&self, zone: Tz) -> Option<(NaiveDate, Option<NaiveTime>)> {
+        match self {
+            TaskType::OnceTask {
+                date,
+                time,
+                zone: home,
+                ..
+            } => Some(date::move_to_zone(*date, *time, *home, zone)),
+            _ => None,
+        }
+    }
+
+    /// The time of day the task is due at in `zone`, if it has one. Week and
+    /// month tasks keep to the wall clock wherever ttd is used.
+    pub fn time_in(&self, zone: Tz) -> Option<NaiveTime> {
+        match self {
+            TaskType::WeekTask { time, .. } | TaskType::MonthTask { time, .. } => *time,
+            TaskType::OnceTask { .. } => self.due_in(zone)?.1,
+            TaskType::ProgressTask { .. } | TaskType::RecurringTask { .. } => None,
+        }
+    }
+}
+
 impl Task {
-    pub fn build(content: TaskType) -> Task {
+    pub fn build(content: TaskType, clock: &Clock) -> Task {
         Task {
             id: 0,
-            created_at: date::get_time(),
+            uid: Uuid::new_v4(),
+            created_at: clock.timestamp(),
+            completed_at: None,
             content,
         }
     }
 
-    fn format_info(&self) -> String {
+    /// Whether the task is done. For repeating tasks this only covers the
+    /// current occurrence, so they come back once the next one starts.
+    /// Days are those of the zone of `now`.
+    pub fn is_done(&self, now: DateTime<Tz>) -> bool {
+        let Some(completed_at) = self.completed_at else {
+            return false;
+        };
+        let today = now.date_naive();
+        let completed_on = completed_at.with_timezone(&now.timezone()).date_naive();
+        match &self.content {
+            TaskType::WeekTask { weekdays, .. } => {
+                date::completed_in_current_occurrence(completed_on, today, |d| {
+                    date::weekday_check(weekdays, d)
+                })
+            }
+            TaskType::MonthTask { day, overflow, .. } => {
+                date::completed_in_current_occurrence(completed_on, today, |d| {
+                    date::day_check(*day, *overflow, d)
+                })
+            }
+            TaskType::RecurringTask { rule, .. } => rule
+                .last_on_or_before(today)
+                .is_some_and(|occurrence| completed_on >= occurrence),
+            TaskType::OnceTask { .. } | TaskType::ProgressTask { .. } => true,
+        }
+    }
+
+    /// The short form of `uid` shown in listings, accepted wherever an id is.
+    pub fn short_uid(&self) -> String {
+        self.uid.simple().to_string()[..8].to_string()
+    }
+
+    /// The line shown for the task in listings, with its state at `now`.
+    /// Dates and times are shown in the zone of `now`.
+    pub fn format_info(&self, now: DateTime<Tz>) -> String {
+        let today = now.date_naive();
+        let created_at = date::format_timestamp(&self.created_at, now.timezone());
+        let done = self.is_done(now);
+        let ongoing = self.content.occurs_on(today, now.timezone());
+        let colors = Colors::current();
         match &self.content {
             TaskType::WeekTask {
                 text,
-                weekday,
-                ongoing,
+                weekdays,
+                time,
             } => {
-                let flag = if *ongoing {
-                    " Ongoing".bright_green()
+                let flag = if done {
+                    " Done".color(colors.done.as_str())
+                } else if ongoing {
+                    " Ongoing".color(colors.ongoing.as_str())
                 } else {
-                    " Upcoming".to_string().bright_yellow()
+                    " Upcoming".color(colors.upcoming.as_str())
                 };
                 format!(
-                    "[#{}]: {} - {}{} - repeat every week - created at {}",
+                    "[#{} {}]: {} - {}{} - repeat every week - created at {}",
                     self.id,
+                    self.short_uid().dimmed(),
                     text.bright_blue(),
-                    weekday.bright_green(),
+                    with_time(date::format_weekdays(weekdays), time).bright_green(),
                     flag,
-                    self.created_at
+                    created_at
                 )
             }
-            TaskType::MonthTask { text, day, ongoing } => {
-                let flag = if *ongoing {
-                    " Ongoing".bright_green()
+            TaskType::MonthTask {
+                text, day, time, ..
+            } => {
+                let flag = if done {
+                    " Done".color(colors.done.as_str())
+                } else if ongoing {
+                    " Ongoing".color(colors.ongoing.as_str())
                 } else {
-                    " Upcoming".to_string().bright_yellow()
+                    " Upcoming".color(colors.upcoming.as_str())
                 };
                 format!(
-                    "[#{}]: {} - {}{} - repeat every month - created at {}",
+                    "[#{} {}]: {} - {}{} - repeat every month - created at {}",
                     self.id,
+                    self.short_uid().dimmed(),
                     text.bright_blue(),
-                    day.to_string().bright_green(),
+                    with_time(date::format_monthday(*day), time).bright_green(),
                     flag,
-                    self.created_at
+                    created_at
                 )
             }
-            TaskType::OnceTask { text, date, status } => {
-                let flag = match status {
-                    OnceDateStatus::Expired => " Expired".bright_red(),
-                    OnceDateStatus::Upcoming => " Upcoming".bright_yellow(),
-                    OnceDateStatus::Ongoing => " Ongoing".bright_green(),
+            TaskType::OnceTask {
+                text,
+                date,
+                time,
+                zone,
+                remind,
+            } => {
+                let (date, time) = date::move_to_zone(*date, *time, *zone, now.timezone());
+                let flag = match date::date_check(date, time, *remind, now.naive_local()) {
+                    _ if done => " Done".color(colors.done.as_str()),
+                    OnceDateStatus::Expired => " Expired".color(colors.expired.as_str()),
+                    OnceDateStatus::DueSoon => " Due soon".color(colors.due_soon.as_str()),
+                    OnceDateStatus::Upcoming => " Upcoming".color(colors.upcoming.as_str()),
+                    OnceDateStatus::Ongoing => " Ongoing".color(colors.ongoing.as_str()),
                 };
                 format!(
-                    "[#{}]: {} - {}{} - once-time reminder - created at {}",
+                    "[#{} {}]: {} - {}{} - once-time reminder - created at {}",
                     self.id,
+                    self.short_uid().dimmed(),
                     text.bright_blue(),
-                    date.bright_green(),
+                    with_time(date.format(date::DATE_FORMAT).to_string(), &time).bright_green(),
                     flag,
-                    self.created_at
+                    created_at
                 )
             }
             TaskType::ProgressTask { text, progress } => {
+                let flag = if done {
+                    " Done".color(colors.done.as_str())
+                } else {
+                    "".normal()
+                };
                 format!(
-                    "[#{}]: {} - {} - progress - created at {}",
+                    "[#{} {}]: {} - {}{} - progress - created at {}",
                     self.id,
+                    self.short_uid().dimmed(),
                     text.bright_blue(),
                     progress.bright_green(),
-                    self.created_at
+                    flag,
+                    created_at
+                )
+            }
+            TaskType::RecurringTask { text, rule } => {
+                let flag = if done {
+                    " Done".color(colors.done.as_str())
+                } else if ongoing {
+                    " Ongoing".color(colors.ongoing.as_str())
+                } else {
+                    " Upcoming".color(colors.upcoming.as_str())
+                };
+                format!(
+                    "[#{} {}]: {} - {}{} - repeat by rule - created at {}",
+                    self.id,
+                    self.short_uid().dimmed(),
+                    text.bright_blue(),
+                    rule.to_string().bright_green(),
+                    flag,
+                    created_at
                 )
             }
         }
     }
 }
 
-impl Display for Task {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.format_info())
+/// Appends the time of day of a task to its weekdays, monthday or date.
+fn with_time(value: String, time: &Option<NaiveTime>) -> String {
+    match time {
+        Some(time) => format!("{} {}", value, time.format(date::TIME_OF_DAY_FORMAT)),
+        None => value,
     }
 }